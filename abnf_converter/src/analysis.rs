@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+
+use crate::grammar::{json_key, to_json_lines, Alternative, Grammar, Symbol};
+
+/// The cheapest derivation of a rule or an alternative; `None` if it can't terminate.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Cost {
+    /// The minimal depth of the derivation tree; a rule with a terminal-only alternative has a
+    /// depth of 1.
+    pub min_depth: Option<usize>,
+    /// The minimal length of the derived text in bytes.
+    pub min_length: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuleCosts {
+    pub cost: Cost,
+    pub alternatives: Vec<Cost>,
+}
+
+/// The minimal derivation costs of every rule of a grammar.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Analysis {
+    pub rules: Vec<(String, RuleCosts)>,
+    indices: HashMap<String, usize>,
+}
+
+impl Analysis {
+    pub fn new(grammar: &Grammar) -> Self {
+        let mut ret = Analysis::default();
+        for rule in &grammar.rules {
+            if ret.indices.contains_key(&rule.name) {
+                continue;
+            }
+            ret.indices.insert(rule.name.clone(), ret.rules.len());
+            ret.rules.push((
+                rule.name.clone(),
+                RuleCosts {
+                    cost: Cost::default(),
+                    alternatives: vec![Cost::default(); rule.alternatives.len()],
+                },
+            ));
+        }
+
+        // the costs can only decrease, so this reaches a fixpoint
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for rule in &grammar.rules {
+                let idx = ret.indices[&rule.name];
+                for (i, alternative) in rule.alternatives.iter().enumerate() {
+                    let cost = ret.alternative_cost(alternative);
+                    let costs = &mut ret.rules[idx].1;
+                    if cost != costs.alternatives[i] {
+                        costs.alternatives[i] = cost;
+                        changed = true;
+                    }
+                    let min_depth = min(costs.cost.min_depth, cost.min_depth);
+                    let min_length = min(costs.cost.min_length, cost.min_length);
+                    if min_depth != costs.cost.min_depth || min_length != costs.cost.min_length {
+                        costs.cost = Cost {
+                            min_depth,
+                            min_length,
+                        };
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        ret
+    }
+
+    pub fn rule(&self, name: &str) -> Option<&RuleCosts> {
+        self.indices.get(name).map(|&idx| &self.rules[idx].1)
+    }
+
+    fn alternative_cost(&self, alternative: &Alternative) -> Cost {
+        let mut max_depth = Some(0);
+        let mut length = Some(0);
+        for symbol in alternative {
+            let cost = match symbol {
+                Symbol::Terminal(s) => Cost {
+                    min_depth: Some(0),
+                    min_length: Some(s.len()),
+                },
+                Symbol::NonTerminal(name) => self.rule(name).map(|r| r.cost).unwrap_or_default(),
+                Symbol::Range(start, _) => Cost {
+                    min_depth: Some(0),
+                    min_length: Some(char::from_u32(*start).map_or(3, |c| c.len_utf8())),
+                },
+            };
+            max_depth = max_depth.zip(cost.min_depth).map(|(a, b)| a.max(b));
+            length = length.zip(cost.min_length).map(|(a, b)| a + b);
+        }
+
+        Cost {
+            min_depth: max_depth.map(|depth| depth + 1),
+            min_length: length,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        to_json_lines(self)
+    }
+}
+
+/// The smaller of two costs, `None` standing for infinity.
+fn min(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+impl Serialize for Cost {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("min_depth", &self.min_depth)?;
+        map.serialize_entry("min_length", &self.min_length)?;
+        map.end()
+    }
+}
+
+struct JsonCosts<'a>(&'a [Cost]);
+
+impl Serialize for JsonCosts<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for cost in self.0 {
+            seq.serialize_element(cost)?;
+        }
+        seq.end()
+    }
+}
+
+impl Serialize for RuleCosts {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(3))?;
+        map.serialize_entry("min_depth", &self.cost.min_depth)?;
+        map.serialize_entry("min_length", &self.cost.min_length)?;
+        map.serialize_entry("alternatives", &JsonCosts(&self.alternatives))?;
+        map.end()
+    }
+}
+
+impl Serialize for Analysis {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.rules.len()))?;
+        for (name, costs) in &self.rules {
+            map.serialize_entry(&json_key(name), costs)?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use abnf::rulelist;
+
+    use super::*;
+    use crate::{options::ConversionOptions, ruleset_to_grammar};
+
+    #[test]
+    fn minimal_costs() {
+        let rules = rulelist(
+            "program = *item\nitem = \"ab\" / \"(\" item \")\" / %x61-62\nloop = \"x\" loop\n",
+        )
+        .unwrap();
+        let grammar = ruleset_to_grammar(&rules, "program", &ConversionOptions::default()).unwrap();
+        let analysis = Analysis::new(&grammar);
+
+        let cost = |depth, length| Cost {
+            min_depth: Some(depth),
+            min_length: Some(length),
+        };
+        let item = analysis.rule("item").unwrap();
+        assert_eq!(item.cost, cost(1, 1));
+        // the range is extracted into a rule of its own
+        assert_eq!(item.alternatives, [cost(1, 2), cost(2, 3), cost(2, 1)]);
+
+        // the zero-or-more repetition can always end right away
+        let repetition = analysis.rule("zero-or-more-items").unwrap();
+        assert_eq!(repetition.alternatives, [cost(1, 0), cost(2, 1)]);
+        assert_eq!(analysis.rule("start").unwrap().cost, cost(3, 0));
+
+        assert_eq!(analysis.rule("loop").unwrap().cost, Cost::default());
+
+        let json = serde_json::from_str::<serde_json::Value>(&analysis.to_json()).unwrap();
+        assert_eq!(json["<item>"]["min_depth"], 1);
+        assert_eq!(
+            json["<loop>"]["alternatives"][0]["min_length"],
+            serde_json::Value::Null
+        );
+    }
+}
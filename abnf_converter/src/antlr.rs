@@ -0,0 +1,540 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+use abnf::types::Rule;
+use anyhow::{bail, Context};
+
+use crate::{
+    grammar::{Alternative, Grammar, GrammarRule, RuleOrigin, Symbol},
+    validation::collect_rule_references,
+};
+
+/// Identifiers that can't be used as ANTLR rule names.
+const ANTLR_KEYWORDS: &[&str] = &[
+    "catch", "channels", "finally", "fragment", "grammar", "import", "lexer", "locals", "mode",
+    "options", "parser", "returns", "throws", "tokens",
+];
+
+/// Derives the ANTLR rule name from the name used in the JSON output; the characters that can't
+/// be used in identifiers become underscores.
+fn antlr_rule_name(json_name: &str, lexical: bool) -> String {
+    let mut name = json_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        name.insert_str(0, "rule_");
+    }
+    let name = if lexical {
+        name.to_ascii_uppercase()
+    } else {
+        name.to_ascii_lowercase()
+    };
+    if ANTLR_KEYWORDS.contains(&name.as_str()) {
+        format!("{name}_")
+    } else {
+        name
+    }
+}
+
+/// Finds the rules that only reference other lexical rules, given the references of every rule.
+fn lexical_names<'a>(references: &[(&'a str, Vec<&'a str>)], start: &str) -> HashSet<&'a str> {
+    let mut lexical = references
+        .iter()
+        .map(|(name, _)| *name)
+        .filter(|&name| name != start)
+        .collect::<HashSet<_>>();
+    loop {
+        let demoted = references
+            .iter()
+            .filter(|(name, refs)| {
+                lexical.contains(name) && refs.iter().any(|r| !lexical.contains(r))
+            })
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        if demoted.is_empty() {
+            break;
+        }
+        for name in demoted {
+            lexical.remove(name);
+        }
+    }
+
+    lexical
+}
+
+/// Finds the rules that only consist of terminals and other lexical rules; they can be expressed
+/// as ANTLR lexer rules. The start rule is always a parser rule.
+pub fn lexical_rules<'a>(rules: &'a [Rule], start: &str) -> HashSet<&'a str> {
+    let references = rules
+        .iter()
+        .map(|rule| {
+            let mut refs = Vec::new();
+            collect_rule_references(rule.node(), &mut refs);
+            (rule.name(), refs)
+        })
+        .collect::<Vec<_>>();
+    lexical_names(&references, start)
+}
+
+/// The lexical rules of the converted grammar; the start symbol of the fuzzers is never one.
+fn lexical_grammar_rules(grammar: &Grammar) -> HashSet<&str> {
+    let references = grammar
+        .rules
+        .iter()
+        .filter(|rule| rule.origin != RuleOrigin::Start)
+        .map(|rule| {
+            let refs = rule
+                .alternatives
+                .iter()
+                .flatten()
+                .filter_map(|symbol| match symbol {
+                    Symbol::NonTerminal(name) => Some(name.as_str()),
+                    _ => None,
+                })
+                .collect();
+            (rule.name.as_str(), refs)
+        })
+        .collect::<Vec<_>>();
+    lexical_names(&references, &grammar.start)
+}
+
+fn escape_char(c: char, in_set: bool, ret: &mut String) {
+    match c {
+        '\\' => ret.push_str("\\\\"),
+        '\n' => ret.push_str("\\n"),
+        '\r' => ret.push_str("\\r"),
+        '\t' => ret.push_str("\\t"),
+        '\'' if !in_set => ret.push_str("\\'"),
+        ']' | '-' if in_set => {
+            ret.push('\\');
+            ret.push(c);
+        }
+        ' '..='~' => ret.push(c),
+        c if (c as u32) <= 0xFFFF => write!(ret, "\\u{:04X}", c as u32).unwrap(),
+        c => write!(ret, "\\u{{{:X}}}", c as u32).unwrap(),
+    }
+}
+
+fn literal(s: &str) -> String {
+    let mut ret = String::from("'");
+    s.chars().for_each(|c| escape_char(c, false, &mut ret));
+    ret.push('\'');
+    ret
+}
+
+/// Converts a range of code points to a character set; the surrogates aren't characters, so the
+/// range is split around them.
+fn char_set(start: u32, end: u32) -> anyhow::Result<String> {
+    let max = end.min(char::MAX as u32);
+    let mut ret = String::from("[");
+    for (first, last) in [(start, max.min(0xD7FF)), (start.max(0xE000), max)] {
+        let (Some(first), Some(last)) = (char::from_u32(first), char::from_u32(last)) else {
+            continue;
+        };
+        if first > last {
+            continue;
+        }
+        escape_char(first, true, &mut ret);
+        if last > first {
+            ret.push('-');
+            escape_char(last, true, &mut ret);
+        }
+    }
+    if ret.len() == 1 {
+        bail!("the range %x{start:X}-{end:X} doesn't contain any character");
+    }
+    ret.push(']');
+    Ok(ret)
+}
+
+/// The repeated symbols of a repetition rule, unless the repetitions are separated.
+fn repeated_symbols(rule: &GrammarRule, min: usize, max: Option<usize>) -> Option<&[Symbol]> {
+    let alternatives = &rule.alternatives;
+    let symbols = match max {
+        // the minimum number of elements, or one more element followed by the rule itself
+        None => match alternatives.as_slice() {
+            [_, more] => match more.split_last() {
+                Some((Symbol::NonTerminal(name), symbols)) if *name == rule.name => symbols,
+                _ => return None,
+            },
+            _ => return None,
+        },
+        // every allowed number of elements
+        Some(max) => {
+            if alternatives.len() != max.checked_sub(min)? + 1 {
+                return None;
+            }
+            match alternatives.as_slice() {
+                [first, second, ..] => second.strip_prefix(first.as_slice())?,
+                [first] if min > 0 => &first[..first.len() / min],
+                _ => return None,
+            }
+        }
+    };
+    if symbols.is_empty() {
+        return None;
+    }
+
+    let repeated = |alternative: &[Symbol], count: usize| {
+        alternative.len() == symbols.len() * count
+            && alternative
+                .chunks(symbols.len())
+                .all(|chunk| chunk == symbols)
+    };
+    let plain = match max {
+        None => repeated(&alternatives[0], min),
+        Some(_) => alternatives
+            .iter()
+            .enumerate()
+            .all(|(idx, alternative)| repeated(alternative, min + idx)),
+    };
+    plain.then_some(symbols)
+}
+
+/// A rendered part of an alternative.
+struct Element {
+    text: String,
+    /// Whether the text can be followed by a suffix operator as is.
+    atom: bool,
+}
+
+struct AntlrConverter<'a> {
+    rules: HashMap<&'a str, &'a GrammarRule>,
+    lexical: HashSet<&'a str>,
+    /// The nested rules that can't be written in place of their references, like the ranges used
+    /// in parser rules and the separated unbounded repetitions; they need rules of their own.
+    own_rules: Vec<&'a str>,
+    /// The lexer rules used directly by parser rules; the other ones are fragments.
+    parser_references: HashSet<&'a str>,
+}
+
+impl<'a> AntlrConverter<'a> {
+    fn rule_name(&self, name: &str) -> String {
+        antlr_rule_name(name, self.lexical.contains(name))
+    }
+
+    /// Renders the alternatives of a rule.
+    fn convert(&mut self, name: &'a str) -> anyhow::Result<Vec<String>> {
+        let lexical = self.lexical.contains(name);
+        self.rules[name]
+            .alternatives
+            .iter()
+            .map(|alternative| self.sequence(alternative, lexical))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .with_context(|| format!("couldn't convert rule `{name}`"))
+    }
+
+    fn sequence(&mut self, symbols: &'a [Symbol], lexical: bool) -> anyhow::Result<String> {
+        let mut elements = Vec::new();
+        for symbol in symbols {
+            let element = self.symbol(symbol, lexical)?;
+            if !element.text.is_empty() {
+                elements.push(element.text);
+            }
+        }
+        Ok(elements.join(" "))
+    }
+
+    /// Renders the symbols so that they can be followed by a suffix operator.
+    fn atom(&mut self, symbols: &'a [Symbol], lexical: bool) -> anyhow::Result<String> {
+        if let [symbol] = symbols {
+            let element = self.symbol(symbol, lexical)?;
+            if element.atom {
+                return Ok(element.text);
+            }
+            return Ok(format!("( {} )", element.text));
+        }
+        Ok(format!("( {} )", self.sequence(symbols, lexical)?))
+    }
+
+    /// Renders the alternatives as a group, which is optional if the first one is empty.
+    fn group(&mut self, alternatives: &'a [Alternative], lexical: bool) -> anyhow::Result<Element> {
+        if alternatives
+            .iter()
+            .all(|alternative| alternative.is_empty())
+        {
+            return Ok(Element {
+                text: String::new(),
+                atom: true,
+            });
+        }
+        let (optional, alternatives) = match alternatives {
+            [first, rest @ ..] if first.is_empty() && !rest.is_empty() => (true, rest),
+            alternatives => (false, alternatives),
+        };
+        let body = alternatives
+            .iter()
+            .map(|alternative| self.sequence(alternative, lexical))
+            .collect::<anyhow::Result<Vec<_>>>()?
+            .join(" | ");
+        Ok(if optional {
+            Element {
+                text: format!("( {body} )?"),
+                atom: false,
+            }
+        } else {
+            Element {
+                text: format!("( {body} )"),
+                atom: true,
+            }
+        })
+    }
+
+    fn symbol(&mut self, symbol: &'a Symbol, lexical: bool) -> anyhow::Result<Element> {
+        let name = match symbol {
+            Symbol::Terminal(s) if s.is_empty() => String::new(),
+            Symbol::Terminal(s) => literal(s),
+            Symbol::Range(start, end) => char_set(*start, *end)?,
+            Symbol::NonTerminal(name) => return self.reference(name, lexical),
+        };
+        Ok(Element {
+            text: name,
+            atom: true,
+        })
+    }
+
+    /// Renders a reference to a rule; the nested rules are written in place of it if possible.
+    fn reference(&mut self, name: &'a str, lexical: bool) -> anyhow::Result<Element> {
+        if let Some(&rule) = self.rules.get(name) {
+            if let Some(element) = self.inline(rule, lexical)? {
+                return Ok(element);
+            }
+            if rule.origin != RuleOrigin::Rule && !self.own_rules.contains(&name) {
+                self.own_rules.push(name);
+            }
+        }
+        if !lexical && self.lexical.contains(name) {
+            self.parser_references.insert(name);
+        }
+        Ok(Element {
+            text: self.rule_name(name),
+            atom: true,
+        })
+    }
+
+    fn inline(&mut self, rule: &'a GrammarRule, lexical: bool) -> anyhow::Result<Option<Element>> {
+        let element = match rule.origin {
+            RuleOrigin::Rule | RuleOrigin::Start => return Ok(None),
+            // parser rules can't contain character sets
+            RuleOrigin::Range(..) if !lexical => return Ok(None),
+            RuleOrigin::Range(..) => match rule.alternatives.as_slice() {
+                [alternative] => Element {
+                    text: self.sequence(alternative, lexical)?,
+                    atom: alternative.len() == 1,
+                },
+                alternatives => self.group(alternatives, lexical)?,
+            },
+            RuleOrigin::Group | RuleOrigin::CaseInsensitiveLetter => {
+                self.group(&rule.alternatives, lexical)?
+            }
+            RuleOrigin::Repetition { min, max } => {
+                let Some(symbols) = repeated_symbols(rule, min, max) else {
+                    // the separated unbounded repetitions reference themselves
+                    if max.is_none() {
+                        return Ok(None);
+                    }
+                    return self.group(&rule.alternatives, lexical).map(Some);
+                };
+                let atom = self.atom(symbols, lexical)?;
+                let mut elements = Vec::new();
+                match max {
+                    Some(max) => {
+                        elements.extend((0..min).map(|_| atom.clone()));
+                        elements.extend((min..max).map(|_| format!("{atom}?")));
+                    }
+                    None if min == 0 => elements.push(format!("{atom}*")),
+                    None => {
+                        elements.extend((1..min).map(|_| atom.clone()));
+                        elements.push(format!("{atom}+"));
+                    }
+                }
+                Element {
+                    text: elements.join(" "),
+                    atom: false,
+                }
+            }
+        };
+        Ok(Some(element))
+    }
+}
+
+/// Converts the grammar to a combined ANTLR4 grammar; rules that only consist of terminals become
+/// lexer rules, and the remaining ones are parser rules. The nested rules are written in place of
+/// their references where ANTLR can express them.
+pub fn grammar_to_antlr(grammar: &Grammar, grammar_name: &str) -> anyhow::Result<String> {
+    let mut rules = HashMap::new();
+    for rule in &grammar.rules {
+        rules.entry(rule.name.as_str()).or_insert(rule);
+    }
+    let mut converter = AntlrConverter {
+        rules,
+        lexical: lexical_grammar_rules(grammar),
+        own_rules: Vec::new(),
+        parser_references: HashSet::new(),
+    };
+
+    // the start rule goes first, followed by the parser and the lexer rules, and by the nested
+    // rules that couldn't be written in place
+    let mut ordered = Vec::new();
+    for rule in grammar
+        .rules
+        .iter()
+        .filter(|r| r.origin == RuleOrigin::Rule)
+    {
+        if !ordered.contains(&rule.name.as_str()) {
+            ordered.push(rule.name.as_str());
+        }
+    }
+    ordered.sort_by_key(|&name| (name != grammar.start, converter.lexical.contains(name)));
+
+    let mut converted = Vec::new();
+    for name in ordered {
+        converted.push((name, converter.convert(name)?));
+    }
+    // converting the nested rules may require more of them
+    let mut idx = 0;
+    while let Some(&name) = converter.own_rules.get(idx) {
+        converted.push((name, converter.convert(name)?));
+        idx += 1;
+    }
+
+    let mut emitted = HashMap::new();
+    let mut ret = format!("grammar {grammar_name};\n");
+    for (rule, alternatives) in converted {
+        let lexical = converter.lexical.contains(rule);
+        let name = converter.rule_name(rule);
+        if let Some(other) = emitted.insert(name.clone(), rule) {
+            bail!("rules `{other}` and `{rule}` would both become the ANTLR rule `{name}`");
+        }
+
+        ret.push('\n');
+        if lexical && !converter.parser_references.contains(rule) {
+            ret.push_str("fragment ");
+        }
+        ret.push_str(&name);
+        for (i, alternative) in alternatives.iter().enumerate() {
+            let separator = if i == 0 { ':' } else { '|' };
+            if alternative.is_empty() {
+                write!(ret, "\n    {separator}").unwrap();
+            } else {
+                write!(ret, "\n    {separator} {alternative}").unwrap();
+            }
+        }
+        ret.push_str("\n    ;\n");
+    }
+
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use abnf::rulelist;
+
+    use super::*;
+    use crate::{
+        options::{CaseMode, ConversionOptions, WhitespaceMode},
+        ruleset_to_grammar,
+        whitespace::WhitespacePolicy,
+    };
+
+    fn antlr(abnf: &str, start: &str, case_mode: CaseMode) -> anyhow::Result<String> {
+        let rules = rulelist(abnf).unwrap();
+        let grammar = ruleset_to_grammar(
+            &rules,
+            start,
+            &ConversionOptions {
+                case_mode,
+                ..Default::default()
+            },
+        )?;
+        grammar_to_antlr(&grammar, "Test")
+    }
+
+    #[test]
+    fn antlr_grammar() {
+        let abnf = r#"
+program = 1*statement [ comment ]
+statement = ( "let" / %s"if" ) 2*4digit *( digit / %x2D-2F ) 3import / "{" program "}"
+comment = "//" *%x20-7E
+digit = %x30-39
+import = "'" / "\"
+"#;
+        let g4 = antlr(abnf, "program", CaseMode::Canonical).unwrap();
+        let expected = r#"grammar Test;
+
+program
+    : statement+ ( COMMENT )?
+    ;
+
+statement
+    : ( 'let' | 'if' ) DIGIT DIGIT DIGIT? DIGIT? ( DIGIT | B45_TO_B47 )* IMPORT IMPORT IMPORT
+    | '{' program '}'
+    ;
+
+COMMENT
+    : '//' [ -~]*
+    ;
+
+DIGIT
+    : [0-9]
+    ;
+
+IMPORT
+    : '\''
+    | '\\'
+    ;
+
+B45_TO_B47
+    : [\--/]
+    ;
+"#;
+        assert_eq!(g4, expected);
+
+        assert_eq!(
+            char_set(0x20, 0x110000).unwrap(),
+            "[ -\\uD7FF\\uE000-\\u{10FFFF}]"
+        );
+        assert_eq!(
+            char_set(0xD000, 0xE000).unwrap(),
+            "[\\uD000-\\uD7FF\\uE000]"
+        );
+        assert!(char_set(0xD800, 0xDFFF).is_err());
+
+        let error = antlr("a = b B\nb = \"x\"\nB = \"y\"\n", "a", CaseMode::Canonical);
+        assert!(error.is_err());
+
+        // the separated repetitions reference themselves
+        let rules = rulelist("list = 1*item\nitem = \"x\"\n").unwrap();
+        let whitespace = WhitespacePolicy {
+            mode: WhitespaceMode::All,
+            ..Default::default()
+        };
+        let grammar = ruleset_to_grammar(
+            &rules,
+            "list",
+            &ConversionOptions {
+                whitespace,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let g4 = grammar_to_antlr(&grammar, "Test").unwrap();
+        assert!(g4.contains("\nlist\n    : AT_LEAST_1_ITEM\n"));
+        assert!(g4.contains("\nAT_LEAST_1_ITEM\n    : ITEM\n    | ITEM ' ' AT_LEAST_1_ITEM\n"));
+    }
+
+    #[test]
+    fn antlr_case_insensitive_strings() {
+        let abnf = "kw = 1*\"in\" %s\"Add\" \"a.b\"\n";
+        let g4 = antlr(abnf, "kw", CaseMode::Canonical).unwrap();
+        assert!(g4.contains("\n    : 'in'+ 'Add' 'a.b'\n"));
+
+        let g4 = antlr(abnf, "kw", CaseMode::Expand).unwrap();
+        assert!(g4.contains(
+            "\n    : ( ( 'i' | 'I' ) ( 'n' | 'N' ) )+ 'Add' ( 'a' | 'A' ) '.' ( 'b' | 'B' )\n"
+        ));
+    }
+}
@@ -0,0 +1,386 @@
+use std::{path::PathBuf, str::FromStr};
+
+use anyhow::{anyhow, bail};
+
+use abnf_converter::{
+    grammar::Symbol,
+    options::{CaseMode, NameStyle, Passes},
+    subset::Subset,
+    whitespace::WhitespacePolicy,
+};
+
+pub const USAGE: &str = "\
+usage: abnf_converter [OPTIONS] [INPUT]
+
+arguments:
+  INPUT                 the ABNF grammar file; stdin is read if missing or `-`
+
+options:
+  -o, --output <PATH>   write the result to a file instead of stdout
+  -s, --start <RULE>    the rule that `<start>` expands to; defaults to the first rule; only the
+                        rules reachable from it are kept if it's given, here or in `--subset`,
+                        or if any rules are excluded or stubbed
+      --exclude <RULE>  remove the rule along with the alternatives that can't be derived
+                        without it; can be given multiple times
+      --stub <RULE>=<TEXT>
+                        replace the rule's definition with the fixed text; can be given
+                        multiple times, the texts becoming alternatives
+      --subset <PATH>   a JSON file with the start rule, the excluded rules and the stubs, e.g.
+                        {\"start\": \"rule\", \"exclude\": [\"rule\"], \"stubs\": {\"rule\": [\"text\"]}}
+  -f, --format <FORMAT> the output format; one of:
+                          json      Fuzzingbook/Grammarinator-style JSON (default)
+                          nautilus  Nautilus/LibAFL JSON rule list
+                          antlr     ANTLR4 grammar, e.g. for Grammarinator
+                          dict      AFL++ dictionary of the reachable terminal strings
+                          grammar-mutator
+                                    JSON grammar checked and adjusted for AFL++'s
+                                    Grammar-Mutator
+  -c, --case-insensitive <MODE>
+                        how case-insensitive string literals are converted; one of:
+                          canonical  keep the case the literal is written in (default)
+                          expand     allow both cases for every letter
+      --names <STYLE>   how the rules extracted from nested groups and repetitions are named;
+                        one of:
+                          descriptive  names derived from the nodes' contents (default)
+                          hashed       short names derived from a hash of the nodes
+      --whitespace <MODE>
+                        the rules whose concatenated elements are separated in the JSON-based
+                        formats and the generated programs; one of:
+                          none       no rules, the elements are glued together (default)
+                          all        every rule
+                          syntactic  the rules that don't consist of terminals alone
+      --separator <TEXT>
+                        the terminal inserted between the separated elements (default: \" \")
+      --separator-rule <RULE>
+                        insert a reference to the rule between the separated elements instead
+      --separate <RULE> separate the rule's elements regardless of `--whitespace`; can be given
+                        multiple times
+      --glue <RULE>     keep the token-level rule, and the rules it uses, glued together; can be
+                        given multiple times
+      --prose <PATH>    a JSON file mapping the grammar's prose values (the text between `<`
+                        and `>`) to lists of replacement terminals
+      --weights <PATH>  a JSON file mapping rules to the relative weights of their alternatives,
+                        e.g. {\"rule\": [3, 1, 0.5]}; the weights can also be given in the grammar
+                        with comments like `; @weights 3 1 0.5` within the rule's definition;
+                        they're used by the generator, and by the JSON format with
+                        `--probabilities`
+      --probabilities   annotate the alternatives of the weighted rules in the JSON format
+                        with their probabilities, e.g. [[\"a\"], {\"prob\": 0.75}]
+      --max-range-size <N>
+                        the number of characters that character ranges are sampled down to
+                        in formats without native ranges (default: 256)
+      --no-core-rules   don't add the RFC 5234 core rules (ALPHA, DIGIT, ...) that are
+                        referenced, but not defined in the grammar
+      --simplify <PASSES>
+                        simplify the grammar before converting it; a comma-separated list of:
+                          inline       inline the rules that are used only once
+                          flatten      remove redundant groups and merge nested alternatives
+                                       and concatenations
+                          dedup        remove duplicate alternatives
+                          unreachable  remove the rules unreachable from the start rule
+                          all          all of the above
+      --allow-invalid   convert the grammar even if its validation reports errors
+      --metadata <PATH> write the minimal derivation depth and length of every rule and
+                        alternative of the JSON grammar to a file
+      --generate <N>    generate N random derivations instead of converting the grammar, and
+                        write them to the `--output` directory
+      --cover           derive a minimal set of programs that covers every alternative of every
+                        rule, and write them to the `--output` directory
+      --k-paths <K>     with `--cover`, also cover every path of K rules in the derivation trees
+      --coverage-report <PATH>
+                        with `--cover`, write a JSON report of the covered targets to a file
+      --parse <DIR>     parse every file in the directory instead of converting the grammar; the
+                        `--output` directory gets the Nautilus grammar, nautilus.json, with the
+                        characters of the files kept in its sampled ranges, the derivation
+                        trees in corpus/ as LibAFL NautilusInputs of that grammar, and the
+                        reasons the other files don't parse in failures/
+      --diff <OLD>      compare the rules of the OLD grammar with the ones of INPUT instead of
+                        converting it, and write a report of the added, removed, renamed and
+                        changed rules
+      --diff-json <PATH>
+                        with `--diff`, also write the report as JSON to a file
+      --seed <N>        the seed of the generator's RNG (default: 0)
+      --max-depth <N>   the maximum depth of the generated derivation trees (default: 32)
+      --max-length <N>  the maximum length of a generated derivation in bytes (default: 4096)
+  -h, --help            print this message
+";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Json,
+    Nautilus,
+    Antlr,
+    Dictionary,
+    GrammarMutator,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "nautilus" => Ok(Self::Nautilus),
+            "antlr" => Ok(Self::Antlr),
+            "dict" => Ok(Self::Dictionary),
+            "grammar-mutator" => Ok(Self::GrammarMutator),
+            _ => bail!("unknown output format `{s}`"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Args {
+    pub input: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub start: Option<String>,
+    pub format: OutputFormat,
+    pub case_mode: CaseMode,
+    pub name_style: NameStyle,
+    pub subset: Subset,
+    pub subset_config: Option<PathBuf>,
+    pub whitespace: WhitespacePolicy,
+    pub prose: Option<PathBuf>,
+    pub weights: Option<PathBuf>,
+    pub probabilities: bool,
+    pub max_range_size: usize,
+    pub no_core_rules: bool,
+    pub simplify: Option<Passes>,
+    pub allow_invalid: bool,
+    pub metadata: Option<PathBuf>,
+    pub generate: Option<usize>,
+    pub cover: bool,
+    pub k_paths: Option<usize>,
+    pub coverage_report: Option<PathBuf>,
+    pub parse: Option<PathBuf>,
+    pub diff: Option<PathBuf>,
+    pub diff_json: Option<PathBuf>,
+    pub seed: u64,
+    pub max_depth: usize,
+    pub max_length: usize,
+    pub help: bool,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            input: None,
+            output: None,
+            start: None,
+            format: OutputFormat::default(),
+            case_mode: CaseMode::default(),
+            name_style: NameStyle::default(),
+            subset: Subset::default(),
+            subset_config: None,
+            whitespace: WhitespacePolicy::default(),
+            prose: None,
+            weights: None,
+            probabilities: false,
+            max_range_size: 256,
+            no_core_rules: false,
+            simplify: None,
+            allow_invalid: false,
+            metadata: None,
+            generate: None,
+            cover: false,
+            k_paths: None,
+            coverage_report: None,
+            parse: None,
+            diff: None,
+            diff_json: None,
+            seed: 0,
+            max_depth: 32,
+            max_length: 4096,
+            help: false,
+        }
+    }
+}
+
+impl Args {
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> anyhow::Result<Self> {
+        let mut ret = Args::default();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => ret.help = true,
+                "--no-core-rules" => ret.no_core_rules = true,
+                "--allow-invalid" => ret.allow_invalid = true,
+                "-o" | "--output" => ret.output = Some(next_value(&arg, &mut args)?.into()),
+                "-s" | "--start" => ret.start = Some(next_value(&arg, &mut args)?),
+                "-f" | "--format" => ret.format = next_value(&arg, &mut args)?.parse()?,
+                "-c" | "--case-insensitive" => {
+                    ret.case_mode = next_value(&arg, &mut args)?.parse()?
+                }
+                "--names" => ret.name_style = next_value(&arg, &mut args)?.parse()?,
+                "--simplify" => ret.simplify = Some(next_value(&arg, &mut args)?.parse()?),
+                "--exclude" => ret.subset.exclude.push(next_value(&arg, &mut args)?),
+                "--stub" => ret.subset.parse_stub(&next_value(&arg, &mut args)?)?,
+                "--subset" => ret.subset_config = Some(next_value(&arg, &mut args)?.into()),
+                "--whitespace" => ret.whitespace.mode = next_value(&arg, &mut args)?.parse()?,
+                "--separator" => {
+                    let separator = next_value(&arg, &mut args)?;
+                    if separator.is_empty() {
+                        bail!("`{arg}` must not be empty");
+                    }
+                    ret.whitespace.separator = Symbol::Terminal(separator);
+                }
+                "--separator-rule" => {
+                    ret.whitespace.separator = Symbol::NonTerminal(next_value(&arg, &mut args)?)
+                }
+                "--separate" => ret.whitespace.separated.push(next_value(&arg, &mut args)?),
+                "--glue" => ret.whitespace.glued.push(next_value(&arg, &mut args)?),
+                "--prose" => ret.prose = Some(next_value(&arg, &mut args)?.into()),
+                "--weights" => ret.weights = Some(next_value(&arg, &mut args)?.into()),
+                "--probabilities" => ret.probabilities = true,
+                "--max-range-size" => {
+                    ret.max_range_size = next_value(&arg, &mut args)?.parse()?;
+                    if ret.max_range_size == 0 {
+                        bail!("`{arg}` must be greater than 0");
+                    }
+                }
+                "--metadata" => ret.metadata = Some(next_value(&arg, &mut args)?.into()),
+                "--generate" => ret.generate = Some(next_value(&arg, &mut args)?.parse()?),
+                "--cover" => ret.cover = true,
+                "--k-paths" => {
+                    let k = next_value(&arg, &mut args)?.parse()?;
+                    if k == 0 {
+                        bail!("`{arg}` must be greater than 0");
+                    }
+                    ret.k_paths = Some(k);
+                }
+                "--coverage-report" => {
+                    ret.coverage_report = Some(next_value(&arg, &mut args)?.into())
+                }
+                "--parse" => ret.parse = Some(next_value(&arg, &mut args)?.into()),
+                "--diff" => ret.diff = Some(next_value(&arg, &mut args)?.into()),
+                "--diff-json" => ret.diff_json = Some(next_value(&arg, &mut args)?.into()),
+                "--seed" => ret.seed = next_value(&arg, &mut args)?.parse()?,
+                "--max-depth" => ret.max_depth = next_value(&arg, &mut args)?.parse()?,
+                "--max-length" => ret.max_length = next_value(&arg, &mut args)?.parse()?,
+                "-" => ret.set_input(arg)?,
+                _ if arg.starts_with('-') => bail!("unknown option `{arg}`"),
+                _ => ret.set_input(arg)?,
+            }
+        }
+
+        let modes = [
+            ret.generate.is_some(),
+            ret.cover,
+            ret.parse.is_some(),
+            ret.diff.is_some(),
+        ];
+        if modes.iter().filter(|&&mode| mode).count() > 1 {
+            bail!("only one of `--generate`, `--cover`, `--parse` and `--diff` can be used");
+        }
+        if ret.parse.is_some() && ret.output.is_none() {
+            bail!("`--parse` requires an `--output` directory");
+        }
+        if ret.generate.is_some() && ret.output.is_none() {
+            bail!("`--generate` requires an `--output` directory");
+        }
+        if ret.cover && ret.output.is_none() {
+            bail!("`--cover` requires an `--output` directory");
+        }
+        if ret.diff.is_none() && ret.diff_json.is_some() {
+            bail!("`--diff-json` requires `--diff`");
+        }
+        if !ret.cover && (ret.k_paths.is_some() || ret.coverage_report.is_some()) {
+            bail!("`--k-paths` and `--coverage-report` require `--cover`");
+        }
+
+        Ok(ret)
+    }
+
+    fn set_input(&mut self, arg: String) -> anyhow::Result<()> {
+        if self.input.is_some() {
+            bail!("only a single input grammar can be provided");
+        }
+        // `-` explicitly selects stdin, which is also the default
+        if arg != "-" {
+            self.input = Some(arg.into());
+        }
+        Ok(())
+    }
+}
+
+fn next_value<I: Iterator<Item = String>>(option: &str, args: &mut I) -> anyhow::Result<String> {
+    args.next()
+        .ok_or_else(|| anyhow!("missing value for `{option}`"))
+}
+
+#[cfg(test)]
+mod tests {
+    use abnf_converter::options::WhitespaceMode;
+
+    use super::*;
+
+    fn parse(args: &[&str]) -> anyhow::Result<Args> {
+        Args::parse(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn arguments() {
+        let args = parse(&["grammar.abnf", "-o", "out.json", "--start", "program"]).unwrap();
+        assert_eq!(args.input, Some("grammar.abnf".into()));
+        assert_eq!(args.output, Some("out.json".into()));
+        assert_eq!(args.start.as_deref(), Some("program"));
+        assert_eq!(args.format, OutputFormat::Json);
+
+        assert_eq!(args.case_mode, CaseMode::Canonical);
+
+        let args = parse(&["-", "-f", "nautilus", "-c", "expand"]).unwrap();
+        assert!(args.input.is_none());
+        assert_eq!(args.format, OutputFormat::Nautilus);
+        assert_eq!(args.case_mode, CaseMode::Expand);
+        assert_eq!(args.name_style, NameStyle::Descriptive);
+        let args = parse(&["--names", "hashed", "--simplify", "flatten,dedup"]).unwrap();
+        assert_eq!(args.name_style, NameStyle::Hashed);
+        let passes = args.simplify.unwrap();
+        assert!(passes.flatten && passes.dedup && !passes.inline && !passes.unreachable);
+        assert!(parse(&["--simplify", "flatten,bogus"]).is_err());
+
+        let args = parse(&["--exclude", "a", "--stub", "b=x=y", "--exclude", "c"]).unwrap();
+        assert_eq!(args.subset.exclude, ["a", "c"]);
+        assert_eq!(args.subset.stubs, [("b".into(), vec!["x=y".into()])]);
+        assert!(parse(&["--stub", "b"]).is_err());
+
+        let args = parse(&[
+            "--whitespace",
+            "syntactic",
+            "--separator-rule",
+            "ws",
+            "--glue",
+            "id",
+        ])
+        .unwrap();
+        assert_eq!(args.whitespace.mode, WhitespaceMode::Syntactic);
+        assert_eq!(args.whitespace.separator, Symbol::NonTerminal("ws".into()));
+        assert_eq!(args.whitespace.glued, ["id"]);
+        assert!(parse(&["--separator", ""]).is_err());
+
+        let args = parse(&["--weights", "weights.json", "--probabilities"]).unwrap();
+        assert_eq!(args.weights, Some("weights.json".into()));
+        assert!(args.probabilities);
+
+        assert!(parse(&["a.abnf", "b.abnf"]).is_err());
+        assert!(parse(&["--start"]).is_err());
+        assert!(parse(&["--bogus"]).is_err());
+        assert!(parse(&["--format", "yaml"]).is_err());
+
+        let args = parse(&["--generate", "10", "--seed", "7", "-o", "seeds"]).unwrap();
+        assert_eq!(args.generate, Some(10));
+        assert_eq!(args.seed, 7);
+        assert!(parse(&["--generate", "10"]).is_err());
+        assert!(parse(&["--cover", "--generate", "10", "-o", "seeds"]).is_err());
+        assert!(parse(&["--k-paths", "2", "-o", "seeds"]).is_err());
+        assert!(parse(&["--parse", "seeds"]).is_err());
+        assert!(parse(&["--parse", "seeds", "--cover", "-o", "trees"]).is_err());
+
+        let args = parse(&["new.abnf", "--diff", "old.abnf", "--diff-json", "diff.json"]).unwrap();
+        assert_eq!(args.diff, Some("old.abnf".into()));
+        assert_eq!(args.diff_json, Some("diff.json".into()));
+        assert!(parse(&["--diff-json", "diff.json"]).is_err());
+        assert!(parse(&["--diff", "old.abnf", "--generate", "1", "-o", "seeds"]).is_err());
+    }
+}
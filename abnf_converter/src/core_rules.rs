@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use abnf::{rulelist, types::Rule};
+
+use crate::validation::{collect_rule_references, rename_references};
+
+/// The core rules defined in RFC 5234, Appendix B.1.
+const CORE_RULES: &str = r#"
+ALPHA = %x41-5A / %x61-7A
+BIT = "0" / "1"
+CHAR = %x01-7F
+CR = %x0D
+CRLF = CR LF
+CTL = %x00-1F / %x7F
+DIGIT = %x30-39
+DQUOTE = %x22
+HEXDIG = DIGIT / "A" / "B" / "C" / "D" / "E" / "F"
+HTAB = %x09
+LF = %x0A
+LWSP = *(WSP / CRLF WSP)
+OCTET = %x00-FF
+SP = %x20
+VCHAR = %x21-7E
+WSP = SP / HTAB
+"#;
+
+/// Appends the core rules that are referenced, but not defined in the grammar. Rule names are
+/// case-insensitive, so the references to rules defined with a different case are renamed to
+/// the definitions, e.g. the `DIGIT` of the core `HEXDIG` to the grammar's `digit`, and the added
+/// rules are named the way they are referenced.
+pub fn add_missing_core_rules(rules: &mut Vec<Rule>) {
+    let core_rules = rulelist(CORE_RULES).unwrap();
+
+    loop {
+        let mut references = Vec::new();
+        for rule in rules.iter() {
+            collect_rule_references(rule.node(), &mut references);
+        }
+
+        let mut renamed = HashMap::new();
+        let mut missing = Vec::new();
+        for reference in references {
+            let is_missing = |r: &Rule| r.name().eq_ignore_ascii_case(reference);
+            if renamed.contains_key(reference) || missing.iter().any(is_missing) {
+                continue;
+            }
+            let defined = rules.iter().find(|r| r.name() == reference).or_else(|| {
+                rules
+                    .iter()
+                    .find(|r| r.name().eq_ignore_ascii_case(reference))
+            });
+            match defined {
+                Some(rule) if rule.name() == reference => {}
+                Some(rule) => {
+                    renamed.insert(reference, rule.name());
+                }
+                None => {
+                    if let Some(core_rule) = core_rules
+                        .iter()
+                        .find(|r| r.name().eq_ignore_ascii_case(reference))
+                    {
+                        missing.push(Rule::new(reference, core_rule.node().clone()));
+                    }
+                }
+            }
+        }
+
+        if renamed.is_empty() && missing.is_empty() {
+            break;
+        }
+        *rules = rules
+            .iter()
+            .map(|rule| Rule::new(rule.name(), rename_references(rule.node(), &renamed)))
+            .chain(missing)
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_rule_injection() {
+        let mut rules = rulelist("number = 1*HEXDIG [ DIGIT ]\ndigit = \"0\"\n").unwrap();
+        add_missing_core_rules(&mut rules);
+
+        // rule names are case-insensitive, so `HEXDIG` uses the grammar's `digit`
+        let names = rules.iter().map(|r| r.name()).collect::<Vec<_>>();
+        assert_eq!(names, ["number", "digit", "HEXDIG"]);
+        let mut references = Vec::new();
+        for rule in &rules {
+            collect_rule_references(rule.node(), &mut references);
+        }
+        assert_eq!(references, ["HEXDIG", "digit", "digit"]);
+
+        // the core rules may reference each other
+        let mut rules = rulelist("newline = crlf\n").unwrap();
+        add_missing_core_rules(&mut rules);
+
+        let names = rules.iter().map(|r| r.name()).collect::<Vec<_>>();
+        assert_eq!(names, ["newline", "crlf", "CR", "LF"]);
+    }
+}
@@ -0,0 +1,522 @@
+use std::collections::{HashMap, HashSet};
+
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+
+use crate::{
+    analysis::Analysis,
+    generator::{feasible_alternatives, GeneratorConfig},
+    grammar::{json_key, to_json_lines, Alternative, CharRange, Grammar, Symbol},
+};
+
+/// The number of consecutive derivations without new coverage after which the enumeration stops.
+const MAX_STALE_DERIVATIONS: usize = 200;
+/// The maximum total number of derivations.
+const MAX_DERIVATIONS: usize = 100_000;
+
+/// Something that a derivation can cover.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Target<'a> {
+    /// An alternative of a rule, by its index.
+    Alternative(&'a str, usize),
+    /// A sequence of rules, each one expanded directly by the previous one.
+    Path(Vec<&'a str>),
+}
+
+/// The result of the coverage-guided enumeration.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    /// A greedily minimized set of derivations that covers all the covered targets.
+    pub seeds: Vec<String>,
+    pub total_alternatives: usize,
+    pub uncovered_alternatives: Vec<(String, usize)>,
+    /// The length of the covered rule paths, if they were requested.
+    pub k: Option<usize>,
+    pub total_paths: usize,
+    pub uncovered_paths: Vec<Vec<String>>,
+}
+
+/// The rule that a target is reached through.
+fn target_rule<'a>(target: &Target<'a>) -> &'a str {
+    match target {
+        Target::Alternative(rule, _) => rule,
+        Target::Path(path) => path[0],
+    }
+}
+
+/// Updates the needy rules after the last pending target of `rule` was covered; only the rules
+/// from which `rule` can be reached may stop being needy.
+fn remove_exhausted<'a>(
+    rule: &'a str,
+    needy: &mut HashSet<&'a str>,
+    pending: &HashMap<&'a str, usize>,
+    references: &HashMap<&'a str, Vec<&'a str>>,
+    referrers: &HashMap<&'a str, Vec<&'a str>>,
+) {
+    let mut affected = HashSet::new();
+    let mut queue = vec![rule];
+    while let Some(rule) = queue.pop() {
+        if affected.insert(rule) {
+            queue.extend(referrers.get(rule).into_iter().flatten().copied());
+        }
+    }
+    needy.retain(|rule| !affected.contains(rule));
+
+    // the affected rules stay needy if they have pending targets of their own, or lead to another
+    // needy rule, possibly through the other affected rules
+    let mut queue = affected
+        .iter()
+        .copied()
+        .filter(|rule| {
+            pending.get(rule).is_some_and(|&count| count != 0)
+                || references
+                    .get(rule)
+                    .into_iter()
+                    .flatten()
+                    .any(|r| needy.contains(r))
+        })
+        .collect::<Vec<_>>();
+    while let Some(rule) = queue.pop() {
+        if needy.insert(rule) {
+            let referrers = referrers.get(rule).into_iter().flatten();
+            queue.extend(referrers.filter(|r| affected.contains(*r)));
+        }
+    }
+}
+
+struct Enumerator<'a> {
+    rules: HashMap<&'a str, &'a [Alternative]>,
+    references: HashMap<&'a str, Vec<&'a str>>,
+    /// The rules referencing every rule.
+    referrers: HashMap<&'a str, Vec<&'a str>>,
+    analysis: Analysis,
+    config: GeneratorConfig,
+    k: Option<usize>,
+    rng: XorShiftRng,
+    targets: HashSet<Target<'a>>,
+    covered: HashSet<Target<'a>>,
+    /// The number of targets of every rule that the previous derivations haven't covered.
+    uncovered: HashMap<&'a str, usize>,
+    /// The rules from which an uncovered target can be reached.
+    uncovered_needy: HashSet<&'a str>,
+    /// The number of targets of every rule that neither the previous derivations nor the current
+    /// one have covered.
+    pending: HashMap<&'a str, usize>,
+    /// The rules from which a pending target can still be reached.
+    needy: HashSet<&'a str>,
+}
+
+impl<'a> Enumerator<'a> {
+    /// Marks the targets of an accepted derivation as covered.
+    fn cover(&mut self, used: &HashSet<Target<'a>>) {
+        for target in used {
+            if !self.covered.insert(target.clone()) {
+                continue;
+            }
+            let rule = target_rule(target);
+            let count = self.uncovered.get_mut(rule).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                remove_exhausted(
+                    rule,
+                    &mut self.uncovered_needy,
+                    &self.uncovered,
+                    &self.references,
+                    &self.referrers,
+                );
+            }
+        }
+    }
+
+    /// Takes a target that the current derivation has just covered into account.
+    fn use_target(&mut self, target: &Target<'a>) {
+        let rule = target_rule(target);
+        let count = self.pending.get_mut(rule).unwrap();
+        *count -= 1;
+        if *count == 0 {
+            remove_exhausted(
+                rule,
+                &mut self.needy,
+                &self.pending,
+                &self.references,
+                &self.referrers,
+            );
+        }
+    }
+
+    /// Returns a derivation along with the targets it covers, or `None` if it exceeded the limits.
+    fn derive(&mut self, start: &'a str) -> Option<(String, HashSet<Target<'a>>)> {
+        let mut ret = String::new();
+        let mut used = HashSet::new();
+        let mut path = Vec::new();
+        self.pending = self.uncovered.clone();
+        self.needy = self.uncovered_needy.clone();
+        self.expand(start, &mut path, &mut used, &mut ret)
+            .then_some((ret, used))
+    }
+
+    fn expand(
+        &mut self,
+        name: &'a str,
+        path: &mut Vec<&'a str>,
+        used: &mut HashSet<Target<'a>>,
+        ret: &mut String,
+    ) -> bool {
+        let (Some(&alternatives), Some(costs)) = (self.rules.get(name), self.analysis.rule(name))
+        else {
+            return false;
+        };
+
+        path.push(name);
+        let mut newly_used = Vec::new();
+        if let Some(k) = self.k {
+            if path.len() >= k {
+                newly_used.push(Target::Path(path[path.len() - k..].to_vec()));
+            }
+        }
+
+        // prefer the uncovered alternatives, then the ones that lead to uncovered targets, and
+        // finally the shortest ones
+        let candidates =
+            feasible_alternatives(costs, self.config.max_depth.saturating_sub(path.len() - 1));
+        let is_new = |idx: &&usize| {
+            let target = Target::Alternative(name, **idx);
+            !self.covered.contains(&target) && !used.contains(&target)
+        };
+        let leads_to_needy = |idx: &&usize| {
+            alternatives[**idx]
+                .iter()
+                .any(|s| matches!(s, Symbol::NonTerminal(nt) if self.needy.contains(nt.as_str())))
+        };
+        let new = candidates
+            .iter()
+            .filter(is_new)
+            .copied()
+            .collect::<Vec<_>>();
+        let needy = candidates
+            .iter()
+            .filter(leads_to_needy)
+            .copied()
+            .collect::<Vec<_>>();
+        let idx = if let Some(&idx) = new.choose(&mut self.rng) {
+            idx
+        } else if let Some(&idx) = needy.choose(&mut self.rng) {
+            idx
+        } else if let Some(&idx) = candidates
+            .iter()
+            .min_by_key(|&&idx| costs.alternatives[idx].min_length)
+        {
+            idx
+        } else {
+            return false;
+        };
+        newly_used.push(Target::Alternative(name, idx));
+        for target in newly_used {
+            if !self.covered.contains(&target) && !used.contains(&target) {
+                self.use_target(&target);
+            }
+            used.insert(target);
+        }
+
+        for symbol in &alternatives[idx] {
+            match symbol {
+                Symbol::Terminal(s) => ret.push_str(s),
+                Symbol::NonTerminal(nt) => {
+                    if !self.expand(nt, path, used, ret) {
+                        return false;
+                    }
+                }
+                Symbol::Range(start, end) => {
+                    let range = CharRange::new(*start, *end);
+                    if range.is_empty() {
+                        return false;
+                    }
+                    ret.push(range.nth(self.rng.gen_range(0..range.len())));
+                }
+            }
+            if ret.len() > self.config.max_length {
+                return false;
+            }
+        }
+
+        path.pop();
+        true
+    }
+}
+
+/// Returns all the paths of `k` rules reachable from the start rule.
+fn rule_paths<'a>(
+    start: &'a str,
+    references: &HashMap<&'a str, Vec<&'a str>>,
+    k: usize,
+) -> HashSet<Vec<&'a str>> {
+    let mut reachable = HashSet::new();
+    let mut queue = vec![start];
+    while let Some(rule) = queue.pop() {
+        if references.contains_key(rule) && reachable.insert(rule) {
+            queue.extend(references.get(rule).into_iter().flatten().copied());
+        }
+    }
+
+    let mut paths = reachable
+        .into_iter()
+        .map(|rule| vec![rule])
+        .collect::<Vec<_>>();
+    for _ in 1..k {
+        paths = paths
+            .into_iter()
+            .flat_map(|path| {
+                let last = *path.last().unwrap();
+                references
+                    .get(last)
+                    .into_iter()
+                    .flatten()
+                    .filter(|r| references.contains_key(*r))
+                    .map(move |&r| {
+                        let mut path = path.clone();
+                        path.push(r);
+                        path
+                    })
+            })
+            .collect();
+    }
+
+    paths.into_iter().collect()
+}
+
+/// Derives programs until every alternative of every rule reachable from the start rule (and,
+/// optionally, every path of `k` rules) is covered, and greedily picks a minimal set of them that
+/// covers the same targets.
+pub fn cover(grammar: &Grammar, config: GeneratorConfig, k: Option<usize>, seed: u64) -> Coverage {
+    let Some(start) = grammar.start_rule().map(|r| r.name.as_str()) else {
+        return Coverage::default();
+    };
+
+    let mut rules = HashMap::new();
+    let mut references = HashMap::new();
+    for rule in &grammar.rules {
+        if rules.contains_key(rule.name.as_str()) {
+            continue;
+        }
+        rules.insert(rule.name.as_str(), rule.alternatives.as_slice());
+        let mut refs = rule
+            .alternatives
+            .iter()
+            .flatten()
+            .filter_map(|s| match s {
+                Symbol::NonTerminal(nt) => Some(nt.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        refs.sort_unstable();
+        refs.dedup();
+        references.insert(rule.name.as_str(), refs);
+    }
+
+    let mut targets = HashSet::new();
+    for path in rule_paths(start, &references, 1) {
+        for idx in 0..rules[path[0]].len() {
+            targets.insert(Target::Alternative(path[0], idx));
+        }
+    }
+    if let Some(k) = k {
+        targets.extend(
+            rule_paths(start, &references, k)
+                .into_iter()
+                .map(Target::Path),
+        );
+    }
+
+    let mut uncovered = HashMap::new();
+    for target in &targets {
+        *uncovered.entry(target_rule(target)).or_insert(0) += 1;
+    }
+    let mut referrers = HashMap::<_, Vec<_>>::new();
+    for (&rule, refs) in &references {
+        for &r in refs {
+            referrers.entry(r).or_default().push(rule);
+        }
+    }
+    let mut uncovered_needy = HashSet::new();
+    let mut queue = uncovered.keys().copied().collect::<Vec<_>>();
+    while let Some(rule) = queue.pop() {
+        if uncovered_needy.insert(rule) {
+            queue.extend(referrers.get(rule).into_iter().flatten().copied());
+        }
+    }
+
+    let mut enumerator = Enumerator {
+        rules,
+        references,
+        referrers,
+        analysis: Analysis::new(grammar),
+        config,
+        k,
+        rng: XorShiftRng::seed_from_u64(seed),
+        targets,
+        covered: HashSet::new(),
+        uncovered,
+        uncovered_needy,
+        pending: HashMap::new(),
+        needy: HashSet::new(),
+    };
+
+    // collect the derivations that cover something new
+    let mut candidates = Vec::new();
+    let mut stale = 0;
+    for _ in 0..MAX_DERIVATIONS {
+        if stale >= MAX_STALE_DERIVATIONS || enumerator.targets.is_subset(&enumerator.covered) {
+            break;
+        }
+        match enumerator.derive(start) {
+            Some((derivation, used)) if !used.is_subset(&enumerator.covered) => {
+                enumerator.cover(&used);
+                candidates.push((derivation, used));
+                stale = 0;
+            }
+            _ => stale += 1,
+        }
+    }
+
+    // greedily pick the derivations that cover the most remaining targets
+    let mut seeds = Vec::new();
+    let mut remaining = enumerator.covered.clone();
+    while !remaining.is_empty() {
+        let (idx, _) = candidates
+            .iter()
+            .enumerate()
+            .max_by_key(|(idx, (_, used))| {
+                (used.intersection(&remaining).count(), usize::MAX - idx)
+            })
+            .unwrap();
+        let (derivation, used) = candidates.swap_remove(idx);
+        remaining.retain(|target| !used.contains(target));
+        seeds.push(derivation);
+    }
+
+    let mut ret = Coverage {
+        seeds,
+        k,
+        ..Default::default()
+    };
+    for target in &enumerator.targets {
+        let covered = enumerator.covered.contains(target);
+        match target {
+            Target::Alternative(rule, idx) => {
+                ret.total_alternatives += 1;
+                if !covered {
+                    ret.uncovered_alternatives.push((rule.to_string(), *idx));
+                }
+            }
+            Target::Path(path) => {
+                ret.total_paths += 1;
+                if !covered {
+                    ret.uncovered_paths
+                        .push(path.iter().map(|r| r.to_string()).collect());
+                }
+            }
+        }
+    }
+    ret.uncovered_alternatives.sort_unstable();
+    ret.uncovered_paths.sort_unstable();
+
+    ret
+}
+
+impl Coverage {
+    pub fn to_json(&self) -> String {
+        to_json_lines(self)
+    }
+}
+
+struct JsonPath<'a>(&'a [String]);
+
+impl Serialize for JsonPath<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for rule in self.0 {
+            seq.serialize_element(&json_key(rule))?;
+        }
+        seq.end()
+    }
+}
+
+impl Serialize for Coverage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let uncovered_alternatives = self
+            .uncovered_alternatives
+            .iter()
+            .map(|(rule, idx)| (json_key(rule), idx))
+            .collect::<Vec<_>>();
+        let uncovered_paths = self
+            .uncovered_paths
+            .iter()
+            .map(|path| JsonPath(path))
+            .collect::<Vec<_>>();
+
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("seeds", &self.seeds.len())?;
+        map.serialize_entry("alternatives", &self.total_alternatives)?;
+        map.serialize_entry("uncovered_alternatives", &uncovered_alternatives)?;
+        if let Some(k) = self.k {
+            map.serialize_entry("k", &k)?;
+            map.serialize_entry("paths", &self.total_paths)?;
+            map.serialize_entry("uncovered_paths", &uncovered_paths)?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use abnf::rulelist;
+
+    use super::*;
+    use crate::{options::ConversionOptions, ruleset_to_grammar};
+
+    #[test]
+    fn alternative_coverage() {
+        let rules = rulelist(
+            r#"
+program = 1*statement
+statement = "let " name / "if " expr " " block / block
+block = "{" *statement "}"
+expr = name / "!" expr / "(" expr ")"
+name = "a" / "b"
+unused = "x"
+"#,
+        )
+        .unwrap();
+        let grammar = ruleset_to_grammar(&rules, "program", &ConversionOptions::default()).unwrap();
+        let config = GeneratorConfig {
+            max_depth: 16,
+            max_length: 256,
+        };
+
+        let coverage = cover(&grammar, config, None, 0);
+        assert!(coverage.uncovered_alternatives.is_empty());
+        assert!(coverage.seeds.len() <= 4, "{:?}", coverage.seeds);
+        for s in ["let ", "if ", "{", "!", "(", "a", "b"] {
+            assert!(coverage.seeds.iter().any(|seed| seed.contains(s)));
+        }
+        assert!(!coverage.seeds.iter().any(|seed| seed.contains('x')));
+
+        let coverage = cover(&grammar, config, Some(2), 0);
+        assert!(coverage.uncovered_alternatives.is_empty());
+        assert!(coverage.total_paths > 0);
+        assert!(
+            coverage.uncovered_paths.is_empty(),
+            "{:?}",
+            coverage.uncovered_paths
+        );
+
+        // the start rule isn't necessarily the first one
+        let mut grammar = grammar;
+        let name = grammar.rules.iter().position(|r| r.name == "name").unwrap();
+        grammar.rules.rotate_left(name);
+        let coverage = cover(&grammar, config, None, 0);
+        assert!(coverage.uncovered_alternatives.is_empty());
+        assert!(coverage.seeds.iter().any(|seed| seed.contains("let ")));
+    }
+}
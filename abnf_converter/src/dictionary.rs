@@ -0,0 +1,202 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+use crate::grammar::{Grammar, GrammarRule, RuleOrigin, Symbol};
+
+/// AFL++ refuses dictionaries with tokens longer than this.
+const MAX_TOKEN_LEN: usize = 128;
+
+/// The lowercase and the uppercase versions of a token, and whether they differ.
+#[derive(Default)]
+struct Token {
+    lowercase: String,
+    uppercase: String,
+    case_insensitive: bool,
+}
+
+impl Token {
+    fn push(self, ret: &mut Vec<String>) {
+        ret.push(self.lowercase);
+        if self.case_insensitive {
+            ret.push(self.uppercase);
+        }
+    }
+}
+
+/// Collects the terminals of the rule, and of the nested rules it uses in place of them; the
+/// letters of the expanded case-insensitive strings are joined with the adjacent terminals.
+fn collect_tokens<'a>(
+    rule: &'a GrammarRule,
+    rules: &HashMap<&str, &'a GrammarRule>,
+    visited: &mut HashSet<&'a str>,
+    ret: &mut Vec<String>,
+) {
+    for alternative in &rule.alternatives {
+        let mut token: Option<Token> = None;
+        let mut after_terminal = false;
+        for symbol in alternative {
+            let letter = match symbol {
+                Symbol::NonTerminal(name) => rules
+                    .get(name.as_str())
+                    .filter(|rule| rule.origin == RuleOrigin::CaseInsensitiveLetter),
+                _ => None,
+            };
+            match (symbol, letter) {
+                // adjacent terminals come from separate strings
+                (Symbol::Terminal(s), _) => {
+                    if after_terminal {
+                        token.take().unwrap().push(ret);
+                    }
+                    let token = token.get_or_insert_with(Token::default);
+                    token.lowercase.push_str(s);
+                    token.uppercase.push_str(s);
+                    after_terminal = true;
+                }
+                (_, Some(letter)) => {
+                    // the rule expands to the lowercase and the uppercase letter
+                    let [lowercase, uppercase] = [0, 1].map(|idx| {
+                        match letter.alternatives.get(idx).and_then(|a| a.first()) {
+                            Some(Symbol::Terminal(c)) => c.as_str(),
+                            _ => "",
+                        }
+                    });
+                    let token = token.get_or_insert_with(Token::default);
+                    token.lowercase.push_str(lowercase);
+                    token.uppercase.push_str(uppercase);
+                    token.case_insensitive = true;
+                    after_terminal = false;
+                }
+                (symbol, None) => {
+                    if let Some(token) = token.take() {
+                        token.push(ret);
+                    }
+                    after_terminal = false;
+                    let Symbol::NonTerminal(name) = symbol else {
+                        continue;
+                    };
+                    let Some(&nested) = rules.get(name.as_str()) else {
+                        continue;
+                    };
+                    if nested.origin != RuleOrigin::Rule && visited.insert(name) {
+                        collect_tokens(nested, rules, visited, ret);
+                    }
+                }
+            }
+        }
+        if let Some(token) = token {
+            token.push(ret);
+        }
+    }
+}
+
+/// Escapes the token for a double-quoted AFL dictionary value.
+fn escape_token(token: &str) -> String {
+    let mut ret = String::with_capacity(token.len() + 2);
+    ret.push('"');
+    for &b in token.as_bytes() {
+        match b {
+            b'"' => ret.push_str("\\\""),
+            b'\\' => ret.push_str("\\\\"),
+            b' '..=b'~' => ret.push(b as char),
+            b => write!(ret, "\\x{b:02X}").unwrap(),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+/// Converts the terminal strings reachable from the start rule to an AFL dictionary (`-x`).
+pub fn grammar_to_dictionary(grammar: &Grammar) -> String {
+    let mut rules = HashMap::new();
+    for rule in &grammar.rules {
+        rules.entry(rule.name.as_str()).or_insert(rule);
+    }
+
+    let mut reachable = HashSet::new();
+    let mut queue = grammar
+        .start_rule()
+        .map(|rule| rule.name.as_str())
+        .into_iter()
+        .collect::<Vec<_>>();
+    while let Some(name) = queue.pop() {
+        if !reachable.insert(name) {
+            continue;
+        }
+        let Some(rule) = rules.get(name) else {
+            continue;
+        };
+        queue.extend(rule.alternatives.iter().flatten().filter_map(|s| match s {
+            Symbol::NonTerminal(name) => Some(name.as_str()),
+            _ => None,
+        }));
+    }
+
+    // the tokens are collected in the order of the rules, with the nested rules in place
+    let mut tokens = Vec::new();
+    let mut visited = HashSet::new();
+    for rule in grammar
+        .rules
+        .iter()
+        .filter(|r| r.origin == RuleOrigin::Rule && reachable.contains(r.name.as_str()))
+    {
+        collect_tokens(rule, &rules, &mut visited, &mut tokens);
+    }
+
+    let mut seen = HashSet::new();
+    let mut ret = String::new();
+    for token in tokens {
+        if token.is_empty() || token.len() > MAX_TOKEN_LEN || seen.contains(&token) {
+            continue;
+        }
+        writeln!(ret, "{}", escape_token(&token)).unwrap();
+        seen.insert(token);
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use abnf::rulelist;
+
+    use super::*;
+    use crate::{
+        options::{CaseMode, ConversionOptions},
+        ruleset_to_grammar,
+    };
+
+    #[test]
+    fn dictionary_tokens() {
+        let rules = rulelist(
+            r#"
+program = 1*( "function" / %s"add.w" / "u64" / quote / %x0A.09 ) [ "" ]
+quote = %x22 / "\" / "function"
+unused = "unused"
+"#,
+        )
+        .unwrap();
+        let dictionary = |case_mode| {
+            let grammar = ruleset_to_grammar(
+                &rules,
+                "program",
+                &ConversionOptions {
+                    case_mode,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            grammar_to_dictionary(&grammar)
+        };
+
+        let dict = dictionary(CaseMode::Canonical);
+        assert_eq!(
+            dict,
+            "\"function\"\n\"add.w\"\n\"u64\"\n\"\\x0A\\x09\"\n\"\\\"\"\n\"\\\\\"\n"
+        );
+
+        let dict = dictionary(CaseMode::Expand);
+        assert!(dict.starts_with("\"function\"\n\"FUNCTION\"\n\"add.w\"\n\"u64\"\n\"U64\"\n"));
+    }
+}
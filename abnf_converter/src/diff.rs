@@ -0,0 +1,265 @@
+use std::{collections::HashMap, fmt};
+
+use abnf::types::Rule;
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+
+use crate::{grammar::to_json_lines, simplify::flatten_rules, validation::rename_references};
+
+/// A rule whose definition differs between the two grammars.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleChange {
+    pub rule: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// The differences between two versions of a grammar; the definitions are compared after
+/// removing the redundant groups, and with the renamed rules replaced.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GrammarDiff {
+    /// The new rules with their definitions.
+    pub added: Vec<(String, String)>,
+    /// The removed rules with their definitions.
+    pub removed: Vec<(String, String)>,
+    /// The old and the new names of the rules with otherwise identical definitions.
+    pub renamed: Vec<(String, String)>,
+    pub changed: Vec<RuleChange>,
+}
+
+impl GrammarDiff {
+    pub fn new(old: &[Rule], new: &[Rule]) -> Self {
+        let flat_old = flatten_rules(old.to_vec());
+        let flat_new = flatten_rules(new.to_vec());
+        let find = |rules: &'_ [Rule], name: &str| rules.iter().position(|r| r.name() == name);
+
+        let mut removed = flat_old
+            .iter()
+            .filter(|r| find(new, r.name()).is_none())
+            .map(|r| r.name())
+            .collect::<Vec<_>>();
+        let mut added = flat_new
+            .iter()
+            .filter(|r| find(old, r.name()).is_none())
+            .map(|r| r.name())
+            .collect::<Vec<_>>();
+
+        // renaming a rule changes the rules referencing it, which may have been renamed too; a
+        // recursive rule references itself by the new name
+        let mut renamed: HashMap<&str, &str> = HashMap::new();
+        let mut renamed_order = Vec::new();
+        loop {
+            let found = removed.iter().enumerate().find_map(|(r_idx, &old_name)| {
+                let old_node = flat_old[find(old, old_name)?].node();
+                added
+                    .iter()
+                    .position(|&new_name| {
+                        let mut candidate = renamed.clone();
+                        candidate.insert(old_name, new_name);
+                        flat_new[find(new, new_name).unwrap()].node()
+                            == &rename_references(old_node, &candidate)
+                    })
+                    .map(|a_idx| (r_idx, a_idx))
+            });
+            let Some((r_idx, a_idx)) = found else {
+                break;
+            };
+            let (old_name, new_name) = (removed.remove(r_idx), added.remove(a_idx));
+            renamed.insert(old_name, new_name);
+            renamed_order.push((old_name, new_name));
+        }
+
+        let changed = flat_new
+            .iter()
+            .filter_map(|rule| {
+                let idx = find(old, rule.name())?;
+                let old_node = rename_references(flat_old[idx].node(), &renamed);
+                (&old_node != rule.node()).then(|| RuleChange {
+                    rule: rule.name().to_owned(),
+                    old: old[idx].to_string(),
+                    new: new[find(new, rule.name()).unwrap()].to_string(),
+                })
+            })
+            .collect();
+
+        let definition = |rules: &[Rule], name: &str| {
+            let rule = &rules[find(rules, name).unwrap()];
+            (name.to_owned(), rule.to_string())
+        };
+        Self {
+            added: added.iter().map(|name| definition(new, name)).collect(),
+            removed: removed.iter().map(|name| definition(old, name)).collect(),
+            renamed: renamed_order
+                .into_iter()
+                .map(|(old, new)| (old.to_owned(), new.to_owned()))
+                .collect(),
+            changed,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.renamed.is_empty()
+            && self.changed.is_empty()
+    }
+
+    pub fn to_json(&self) -> String {
+        to_json_lines(self)
+    }
+}
+
+impl fmt::Display for GrammarDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "the grammars are identical");
+        }
+        for (rule, definition) in &self.added {
+            writeln!(f, "added `{rule}`:\n+ {definition}")?;
+        }
+        for (rule, definition) in &self.removed {
+            writeln!(f, "removed `{rule}`:\n- {definition}")?;
+        }
+        for (old, new) in &self.renamed {
+            writeln!(f, "renamed `{old}` to `{new}`")?;
+        }
+        for change in &self.changed {
+            writeln!(
+                f,
+                "changed `{}`:\n- {}\n+ {}",
+                change.rule, change.old, change.new
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A list of JSON objects with the given keys.
+struct Entries<'a, const N: usize>([&'static str; N], Vec<[&'a str; N]>);
+
+impl<const N: usize> Serialize for Entries<'_, N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.1.len()))?;
+        for values in &self.1 {
+            seq.serialize_element(&Entry(&self.0, values))?;
+        }
+        seq.end()
+    }
+}
+
+struct Entry<'a, const N: usize>(&'a [&'static str; N], &'a [&'a str; N]);
+
+impl<const N: usize> Serialize for Entry<'_, N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(N))?;
+        for (key, value) in self.0.iter().zip(self.1) {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+fn definitions(rules: &[(String, String)]) -> Entries<'_, 2> {
+    let values = rules
+        .iter()
+        .map(|(rule, definition)| [rule.as_str(), definition.as_str()])
+        .collect();
+    Entries(["rule", "definition"], values)
+}
+
+impl Serialize for GrammarDiff {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(4))?;
+        map.serialize_entry("added", &definitions(&self.added))?;
+        map.serialize_entry("removed", &definitions(&self.removed))?;
+        map.serialize_entry(
+            "renamed",
+            &Entries(
+                ["old", "new"],
+                self.renamed
+                    .iter()
+                    .map(|(old, new)| [old.as_str(), new.as_str()])
+                    .collect(),
+            ),
+        )?;
+        map.serialize_entry(
+            "changed",
+            &Entries(
+                ["rule", "old", "new"],
+                self.changed
+                    .iter()
+                    .map(|c| [c.rule.as_str(), c.old.as_str(), c.new.as_str()])
+                    .collect(),
+            ),
+        )?;
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use abnf::rulelist;
+
+    use super::*;
+
+    #[test]
+    fn grammar_diff() {
+        let old = rulelist(
+            r#"
+program = 1*item
+item = function / import
+function = "function " name
+import = "import " name
+name = 1*letter
+letter = %x61-7A
+list = name / name "," list
+"#,
+        )
+        .unwrap();
+        let new = rulelist(
+            r#"
+program = 1*item
+item = function / ( struct )
+function = "function " identifier [ "async" ]
+struct = "struct " identifier
+identifier = 1*lowercase
+lowercase = %x61-7A
+identifiers = identifier / identifier "," identifiers
+"#,
+        )
+        .unwrap();
+
+        let diff = GrammarDiff::new(&old, &new);
+        assert_eq!(
+            diff.renamed,
+            [
+                ("letter".to_owned(), "lowercase".to_owned()),
+                ("name".to_owned(), "identifier".to_owned()),
+                ("list".to_owned(), "identifiers".to_owned())
+            ]
+        );
+        assert_eq!(
+            diff.added,
+            [(
+                "struct".to_owned(),
+                "struct = \"struct \" identifier".to_owned()
+            )]
+        );
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(
+            diff.changed
+                .iter()
+                .map(|c| c.rule.as_str())
+                .collect::<Vec<_>>(),
+            ["item", "function"]
+        );
+        assert!(diff.to_string().contains(
+            "changed `item`:\n- item = function / import\n+ item = function / (struct)\n"
+        ));
+
+        let json: serde_json::Value = serde_json::from_str(&diff.to_json()).unwrap();
+        assert_eq!(json["renamed"][1]["new"], "identifier");
+        assert_eq!(json["removed"][0]["rule"], "import");
+
+        assert!(GrammarDiff::new(&old, &old).is_empty());
+    }
+}
@@ -0,0 +1,888 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt, iter,
+    ops::Range,
+    str,
+};
+
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+use crate::{
+    grammar::{sample_range_keeping, to_json_lines, Grammar, Symbol},
+    nautilus::NautilusTree,
+    START_RULE,
+};
+
+/// A grammar symbol with the rule references resolved to indices.
+#[derive(Debug, Clone, Copy)]
+enum Sym<'a> {
+    Terminal(&'a str),
+    /// `None` for references to undefined rules, which never match.
+    Rule(Option<usize>),
+    Range(u32, u32),
+}
+
+/// An alternative of a rule, parsed up to `dot`, starting at `origin`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Item {
+    rule: usize,
+    alt: usize,
+    dot: usize,
+    origin: usize,
+}
+
+/// How the symbol before the dot of an item was matched, i.e. the first way the item was added;
+/// the links only lead to items that were added before, so following them always ends.
+#[derive(Debug, Clone, Copy)]
+enum Link {
+    /// The item was predicted, with its dot at the beginning.
+    Predicted,
+    /// The item advanced from the item `prev` of the set `start` over a terminal or a range.
+    Scanned { start: usize, prev: usize },
+    /// The item advanced from the item `prev` of the set `start` over a rule, which the completed
+    /// item `child` of the item's own set derives.
+    Completed {
+        start: usize,
+        prev: usize,
+        child: usize,
+    },
+    /// The item advanced from the item `prev` of its own set over a nullable rule.
+    Nullable { prev: usize },
+}
+
+#[derive(Default)]
+struct ItemSet {
+    items: Vec<(Item, Link)>,
+    seen: HashSet<Item>,
+    /// The items with a reference to the rule after their dot.
+    waiting: HashMap<usize, Vec<usize>>,
+}
+
+/// How a symbol of a completed item was matched.
+#[derive(Debug, Clone, Copy)]
+enum Child {
+    /// A terminal or a range, matching the input between the offsets.
+    Text(usize, usize),
+    /// A rule, derived by the completed item of the set.
+    Rule(usize, usize),
+    /// A nullable rule, deriving the empty string.
+    Empty(usize),
+}
+
+/// A right-recursive rule `R = A / B R`, like the ones extracted from unbounded repetitions; the
+/// Earley algorithm takes quadratic time for long right-recursive derivations, but linear time
+/// for left-recursive ones, so the parser matches `R = H A` with the helper rule `H = "" / H B`
+/// instead, and converts the derivations back.
+#[derive(Debug, Clone, Copy)]
+struct Repetition {
+    /// The alternative `A`.
+    base: usize,
+    /// The alternative `B R`.
+    repeated: usize,
+}
+
+/// Finds the alternatives `A` and `B R` of a rule `R = A / B R`, in which `A` and `B` don't
+/// refer to `R`, and `B` isn't empty.
+fn right_recursion(rule: usize, alternatives: &[Vec<Sym>]) -> Option<Repetition> {
+    if alternatives.len() != 2 {
+        return None;
+    }
+    let refers = |symbols: &[Sym]| {
+        symbols
+            .iter()
+            .any(|symbol| matches!(symbol, Sym::Rule(Some(r)) if *r == rule))
+    };
+    (0..2)
+        .map(|base| Repetition {
+            base,
+            repeated: 1 - base,
+        })
+        .find(
+            |repetition| match alternatives[repetition.repeated].split_last() {
+                Some((Sym::Rule(Some(r)), prefix)) => {
+                    *r == rule
+                        && !prefix.is_empty()
+                        && !refers(prefix)
+                        && !refers(&alternatives[repetition.base])
+                }
+                _ => false,
+            },
+        )
+}
+
+/// Finds an alternative of every nullable rule that derives the empty string without recursion.
+fn empty_alternatives(alternatives: &[Vec<Vec<Sym>>]) -> Vec<Option<usize>> {
+    let mut empty = vec![None; alternatives.len()];
+    loop {
+        let mut changed = false;
+        for (idx, alts) in alternatives.iter().enumerate() {
+            if empty[idx].is_some() {
+                continue;
+            }
+            // only the rules found before can be used, so the derivations can't recurse
+            empty[idx] = alts.iter().position(|alt| {
+                alt.iter().all(|sym| match sym {
+                    Sym::Terminal(s) => s.is_empty(),
+                    Sym::Rule(Some(r)) => empty[*r].is_some(),
+                    _ => false,
+                })
+            });
+            changed |= empty[idx].is_some();
+        }
+        if !changed {
+            return empty;
+        }
+    }
+}
+
+/// A derivation tree of the input in the grammar before its ranges are sampled, with its nodes in
+/// pre-order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseTree {
+    pub nodes: Vec<ParseNode>,
+}
+
+/// A node of a derivation tree: a rule derived with one of its alternatives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseNode {
+    /// The index of the rule in the grammar.
+    pub rule: usize,
+    pub alternative: usize,
+    /// The characters that the ranges of the alternative matched.
+    pub characters: Vec<char>,
+    /// The index of the parent, `None` for the root.
+    pub parent: Option<usize>,
+    /// The number of nodes in the subtree, including this one.
+    pub size: usize,
+}
+
+impl ParseTree {
+    /// The indices of the children of the node, one per rule reference of its alternative.
+    pub fn children(&self, node: usize) -> Vec<usize> {
+        let end = node + self.nodes[node].size;
+        let mut ret = Vec::new();
+        let mut child = node + 1;
+        while child < end {
+            ret.push(child);
+            child += self.nodes[child].size;
+        }
+        ret
+    }
+}
+
+/// Maps the derivation trees to trees of the Nautilus rules, in which the ranges are sampled: a
+/// range that is the sole symbol of an alternative becomes an alternative per sampled character,
+/// and any other range a reference to a rule of its own.
+pub struct NautilusIds<'a> {
+    grammar: &'a Grammar,
+    sampled: Grammar,
+    sampled_indices: HashMap<String, usize>,
+    /// The index of the first sampled alternative of every alternative of every rule.
+    offsets: Vec<Vec<usize>>,
+    /// The Nautilus rule id of the first alternative of every sampled rule.
+    first_ids: Vec<usize>,
+}
+
+impl<'a> NautilusIds<'a> {
+    /// Samples the ranges of the grammar down to `max_range_size` characters, keeping the `kept`
+    /// ones, e.g. the characters of the inputs to parse.
+    pub fn new(grammar: &'a Grammar, max_range_size: usize, kept: &BTreeSet<char>) -> Self {
+        let offsets = grammar
+            .rules
+            .iter()
+            .map(|rule| {
+                let mut offset = 0;
+                rule.alternatives
+                    .iter()
+                    .map(|alternative| {
+                        let first = offset;
+                        offset += match alternative.as_slice() {
+                            [Symbol::Range(start, end)] => {
+                                sample_range_keeping(*start, *end, max_range_size, kept).len()
+                            }
+                            _ => 1,
+                        };
+                        first
+                    })
+                    .collect()
+            })
+            .collect();
+        let sampled = grammar.sample_ranges_keeping(max_range_size, kept);
+        let mut sampled_indices = HashMap::new();
+        for (idx, rule) in sampled.rules.iter().enumerate() {
+            sampled_indices.entry(rule.name.clone()).or_insert(idx);
+        }
+        let first_ids = sampled
+            .rules
+            .iter()
+            .scan(0, |id, rule| {
+                let first = *id;
+                *id += rule.alternatives.len();
+                Some(first)
+            })
+            .collect();
+
+        Self {
+            grammar,
+            sampled,
+            sampled_indices,
+            offsets,
+            first_ids,
+        }
+    }
+
+    /// The grammar with its ranges sampled, whose Nautilus rules the trees refer to.
+    pub fn sampled(&self) -> &Grammar {
+        &self.sampled
+    }
+
+    /// Maps the tree to the Nautilus rules; `None` if the tree contains characters that weren't
+    /// sampled from their ranges.
+    pub fn tree(&self, tree: &ParseTree) -> Option<NautilusTree> {
+        enum Pending {
+            Node(usize),
+            /// A character matched by a range with a rule of its own.
+            Character(usize, char),
+        }
+
+        let (mut rules, mut paren) = (Vec::new(), Vec::new());
+        let mut stack = vec![(Pending::Node(0), 0)];
+        while let Some((pending, parent)) = stack.pop() {
+            let idx = match pending {
+                Pending::Node(idx) => idx,
+                Pending::Character(rule, c) => {
+                    let alternatives = 0..self.sampled.rules[rule].alternatives.len();
+                    rules.push(self.character_id(rule, alternatives, c)?);
+                    paren.push(parent);
+                    continue;
+                }
+            };
+            // the sampling keeps the rules in place
+            let node = &tree.nodes[idx];
+            let offset = self.offsets[node.rule][node.alternative];
+            let symbols = &self.grammar.rules[node.rule].alternatives[node.alternative];
+            if let [Symbol::Range(..)] = symbols.as_slice() {
+                let end = self.offsets[node.rule]
+                    .get(node.alternative + 1)
+                    .copied()
+                    .unwrap_or(self.sampled.rules[node.rule].alternatives.len());
+                rules.push(self.character_id(node.rule, offset..end, *node.characters.first()?)?);
+                paren.push(parent);
+                continue;
+            }
+
+            let id = rules.len();
+            rules.push(self.first_ids[node.rule] + offset);
+            paren.push(parent);
+            // the children go in between the references to the ranges' rules
+            let sampled = &self.sampled.rules[node.rule].alternatives[offset];
+            let mut children = tree.children(idx).into_iter();
+            let mut characters = node.characters.iter();
+            let mut pending = Vec::new();
+            for (symbol, sampled) in symbols.iter().zip(sampled) {
+                match (symbol, sampled) {
+                    (Symbol::Range(..), Symbol::NonTerminal(range_rule)) => {
+                        let range_rule = *self.sampled_indices.get(range_rule)?;
+                        pending.push(Pending::Character(range_rule, *characters.next()?));
+                    }
+                    (Symbol::NonTerminal(_), _) => pending.push(Pending::Node(children.next()?)),
+                    _ => {}
+                }
+            }
+            stack.extend(pending.into_iter().rev().map(|pending| (pending, id)));
+        }
+
+        Some(NautilusTree::new(rules, paren))
+    }
+
+    /// The id of the sampled alternative of the rule that consists of the character.
+    fn character_id(&self, rule: usize, alternatives: Range<usize>, c: char) -> Option<usize> {
+        let c = c.to_string();
+        let idx = self.sampled.rules[rule].alternatives[alternatives.clone()]
+            .iter()
+            .position(
+                |alternative| matches!(alternative.as_slice(), [Symbol::Terminal(s)] if *s == c),
+            )?;
+        Some(self.first_ids[rule] + alternatives.start + idx)
+    }
+}
+
+/// The furthest point the parser got to in an input that doesn't match the grammar.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseFailure {
+    /// The byte offset of the failure.
+    pub offset: usize,
+    /// The 1-based line of the failure.
+    pub line: usize,
+    /// The 1-based column of the failure, in characters.
+    pub column: usize,
+    /// The terminals that could have continued the input, ranges written like `%x61-7A`.
+    pub expected: Vec<String>,
+    /// Whether the input isn't valid UTF-8 from the offset on.
+    pub invalid_utf8: bool,
+}
+
+impl fmt::Display for ParseFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: ", self.line, self.column)?;
+        if self.invalid_utf8 {
+            write!(f, "invalid UTF-8")
+        } else if self.expected.is_empty() {
+            write!(f, "unexpected input")
+        } else {
+            write!(f, "expected one of {}", self.expected.join(", "))
+        }
+    }
+}
+
+impl ParseFailure {
+    fn new(input: &str, offset: usize, expected: Vec<String>) -> Self {
+        let before = &input[..offset];
+        let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+        Self {
+            offset,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            expected,
+            invalid_utf8: false,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        to_json_lines(self)
+    }
+}
+
+impl Serialize for ParseFailure {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(5))?;
+        map.serialize_entry("offset", &self.offset)?;
+        map.serialize_entry("line", &self.line)?;
+        map.serialize_entry("column", &self.column)?;
+        map.serialize_entry("expected", &self.expected)?;
+        map.serialize_entry("invalid_utf8", &self.invalid_utf8)?;
+        map.end()
+    }
+}
+
+/// An Earley parser of the inputs of a grammar; the ranges match any of their characters, so the
+/// grammar's ranges shouldn't be sampled.
+pub struct EarleyParser<'a> {
+    grammar: &'a Grammar,
+    /// The alternatives of the rules, followed by the ones of the repetitions' helper rules.
+    alternatives: Vec<Vec<Vec<Sym<'a>>>>,
+    /// The right-recursive rules, which are matched with a helper rule.
+    repetitions: Vec<Option<Repetition>>,
+    start: Option<usize>,
+    nullable: Vec<bool>,
+    /// An alternative of every nullable rule of the grammar that derives the empty string, along
+    /// with the derivations of its symbols.
+    empty: Vec<Option<(usize, Vec<Child>)>>,
+}
+
+/// The derivations left to add to a tree.
+enum Task {
+    /// The derivation of a completed item.
+    Item {
+        set: usize,
+        idx: usize,
+        parent: Option<usize>,
+    },
+    /// The derivation of the empty string by a nullable rule.
+    Empty { rule: usize, parent: Option<usize> },
+    /// The derivation of a right-recursive rule, as matched by its helper rule: the children of
+    /// the remaining repetitions, the last one first, and the children of the base alternative.
+    Repetition {
+        rule: usize,
+        repetitions: Vec<Vec<Child>>,
+        base: Vec<Child>,
+        parent: Option<usize>,
+    },
+}
+
+impl<'a> EarleyParser<'a> {
+    pub fn new(grammar: &'a Grammar) -> Self {
+        let mut indices = HashMap::new();
+        for (idx, rule) in grammar.rules.iter().enumerate() {
+            indices.entry(rule.name.as_str()).or_insert(idx);
+        }
+
+        let resolved = grammar
+            .rules
+            .iter()
+            .map(|rule| {
+                rule.alternatives
+                    .iter()
+                    .map(|alternative| {
+                        alternative
+                            .iter()
+                            .map(|symbol| match symbol {
+                                Symbol::Terminal(s) => Sym::Terminal(s),
+                                Symbol::NonTerminal(nt) => {
+                                    Sym::Rule(indices.get(nt.as_str()).copied())
+                                }
+                                Symbol::Range(start, end) => Sym::Range(*start, *end),
+                            })
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect::<Vec<Vec<Vec<_>>>>();
+
+        let mut alternatives = resolved.clone();
+        let mut repetitions = vec![None; resolved.len()];
+        for (idx, alts) in resolved.iter().enumerate() {
+            let Some(repetition) = right_recursion(idx, alts) else {
+                continue;
+            };
+            let helper = Sym::Rule(Some(alternatives.len()));
+            let (_, repeated) = alts[repetition.repeated].split_last().unwrap();
+            alternatives[idx] = vec![iter::once(helper)
+                .chain(alts[repetition.base].iter().copied())
+                .collect()];
+            alternatives.push(vec![
+                Vec::new(),
+                iter::once(helper).chain(repeated.iter().copied()).collect(),
+            ]);
+            repetitions[idx] = Some(repetition);
+        }
+
+        Self {
+            grammar,
+            start: indices.get(START_RULE).copied(),
+            nullable: empty_alternatives(&alternatives)
+                .iter()
+                .map(Option::is_some)
+                .collect(),
+            empty: empty_alternatives(&resolved)
+                .into_iter()
+                .zip(&resolved)
+                .map(|(alternative, alts)| {
+                    let children = |alt: usize| {
+                        alts[alt]
+                            .iter()
+                            .map(|symbol| match symbol {
+                                Sym::Rule(Some(rule)) => Child::Empty(*rule),
+                                _ => Child::Text(0, 0),
+                            })
+                            .collect()
+                    };
+                    alternative.map(|alt| (alt, children(alt)))
+                })
+                .collect(),
+            alternatives,
+            repetitions,
+        }
+    }
+
+    fn add(&self, set: &mut ItemSet, item: Item, link: Link) {
+        if !set.seen.insert(item) {
+            return;
+        }
+        if let Some(Sym::Rule(Some(rule))) = self.alternatives[item.rule][item.alt].get(item.dot) {
+            set.waiting.entry(*rule).or_default().push(set.items.len());
+        }
+        set.items.push((item, link));
+    }
+
+    /// Parses the input starting from the `<start>` rule.
+    pub fn parse(&self, input: &str) -> Result<ParseTree, ParseFailure> {
+        let mut sets = (0..=input.len())
+            .map(|_| ItemSet::default())
+            .collect::<Vec<_>>();
+        if let Some(start) = self.start {
+            for alt in 0..self.alternatives[start].len() {
+                let item = Item {
+                    rule: start,
+                    alt,
+                    dot: 0,
+                    origin: 0,
+                };
+                self.add(&mut sets[0], item, Link::Predicted);
+            }
+        }
+
+        for pos in 0..=input.len() {
+            let mut i = 0;
+            while i < sets[pos].items.len() {
+                let (item, _) = sets[pos].items[i];
+                let current = i;
+                i += 1;
+                let advanced = Item {
+                    dot: item.dot + 1,
+                    ..item
+                };
+
+                match self.alternatives[item.rule][item.alt].get(item.dot) {
+                    None => {
+                        let waiting = sets[item.origin]
+                            .waiting
+                            .get(&item.rule)
+                            .map_or(0, Vec::len);
+                        for j in 0..waiting {
+                            let prev = sets[item.origin].waiting[&item.rule][j];
+                            let (waiting, _) = sets[item.origin].items[prev];
+                            let link = Link::Completed {
+                                start: item.origin,
+                                prev,
+                                child: current,
+                            };
+                            let item = Item {
+                                dot: waiting.dot + 1,
+                                ..waiting
+                            };
+                            self.add(&mut sets[pos], item, link);
+                        }
+                    }
+                    Some(Sym::Rule(Some(rule))) => {
+                        for alt in 0..self.alternatives[*rule].len() {
+                            let item = Item {
+                                rule: *rule,
+                                alt,
+                                dot: 0,
+                                origin: pos,
+                            };
+                            self.add(&mut sets[pos], item, Link::Predicted);
+                        }
+                        // the completion of a nullable rule may have happened already
+                        if self.nullable[*rule] {
+                            self.add(&mut sets[pos], advanced, Link::Nullable { prev: current });
+                        }
+                    }
+                    Some(Sym::Rule(None)) => {}
+                    Some(Sym::Terminal(s)) => {
+                        if input[pos..].starts_with(s) {
+                            let link = Link::Scanned {
+                                start: pos,
+                                prev: current,
+                            };
+                            self.add(&mut sets[pos + s.len()], advanced, link);
+                        }
+                    }
+                    Some(Sym::Range(start, end)) => {
+                        if let Some(c) = input[pos..].chars().next() {
+                            if (*start..=*end).contains(&(c as u32)) {
+                                let link = Link::Scanned {
+                                    start: pos,
+                                    prev: current,
+                                };
+                                self.add(&mut sets[pos + c.len_utf8()], advanced, link);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let end = input.len();
+        let accepted = sets[end].items.iter().position(|(item, _)| {
+            Some(item.rule) == self.start
+                && item.origin == 0
+                && item.dot == self.alternatives[item.rule][item.alt].len()
+        });
+        match accepted {
+            Some(idx) => Ok(self.build(&sets, input, idx)),
+            None => Err(self.failure(&sets, input)),
+        }
+    }
+
+    /// Parses the input like [`EarleyParser::parse`]; an input that isn't valid UTF-8 fails where
+    /// the invalid bytes begin, unless parsing fails before that.
+    pub fn parse_bytes(&self, input: &[u8]) -> Result<ParseTree, ParseFailure> {
+        let error = match str::from_utf8(input) {
+            Ok(input) => return self.parse(input),
+            Err(error) => error,
+        };
+        let valid = str::from_utf8(&input[..error.valid_up_to()]).unwrap();
+        let mut failure = match self.parse(valid) {
+            Err(failure) if failure.offset < valid.len() => return Err(failure),
+            Err(failure) => failure,
+            Ok(_) => ParseFailure::new(valid, valid.len(), Vec::new()),
+        };
+        failure.invalid_utf8 = true;
+        Err(failure)
+    }
+
+    fn failure(&self, sets: &[ItemSet], input: &str) -> ParseFailure {
+        let offset = sets
+            .iter()
+            .rposition(|set| !set.items.is_empty())
+            .unwrap_or(0);
+
+        let mut expected = Vec::new();
+        for (item, _) in &sets[offset].items {
+            let symbol = match self.alternatives[item.rule][item.alt].get(item.dot) {
+                Some(Sym::Terminal(s)) if !s.is_empty() => format!("{s:?}"),
+                Some(Sym::Range(start, end)) => format!("%x{start:X}-{end:X}"),
+                _ => continue,
+            };
+            if !expected.contains(&symbol) {
+                expected.push(symbol);
+            }
+        }
+        expected.sort();
+
+        ParseFailure::new(input, offset, expected)
+    }
+
+    /// How the symbols of the completed item `idx` of the set were matched, following its links
+    /// back to its prediction.
+    fn children(&self, sets: &[ItemSet], mut set: usize, mut idx: usize) -> Vec<Child> {
+        let mut ret = Vec::new();
+        loop {
+            let (item, link) = sets[set].items[idx];
+            let (child, prev) = match link {
+                Link::Predicted => break,
+                Link::Scanned { start, prev } => (Child::Text(start, set), (start, prev)),
+                Link::Completed { start, prev, child } => (Child::Rule(set, child), (start, prev)),
+                Link::Nullable { prev } => {
+                    let Sym::Rule(Some(rule)) =
+                        self.alternatives[item.rule][item.alt][item.dot - 1]
+                    else {
+                        unreachable!("only the rules are nullable");
+                    };
+                    (Child::Empty(rule), (set, prev))
+                }
+            };
+            ret.push(child);
+            (set, idx) = prev;
+        }
+        ret.reverse();
+        ret
+    }
+
+    /// Builds the derivation tree of the accepted item `idx` of the last set, converting the
+    /// derivations of the helper rules back to the right-recursive ones of the grammar.
+    fn build(&self, sets: &[ItemSet], input: &str, idx: usize) -> ParseTree {
+        let mut nodes = Vec::<ParseNode>::new();
+        let mut tasks = vec![Task::Item {
+            set: sets.len() - 1,
+            idx,
+            parent: None,
+        }];
+
+        while let Some(task) = tasks.pop() {
+            let (rule, alternative, children, parent) = match task {
+                Task::Item { set, idx, parent } => {
+                    let (item, _) = sets[set].items[idx];
+                    let children = self.children(sets, set, idx);
+                    if self.repetitions[item.rule].is_none() {
+                        (item.rule, item.alt, children, parent)
+                    } else {
+                        // every completion of the helper rule adds one repetition, the last one
+                        // first
+                        let (helper, base) = children.split_first().unwrap();
+                        let mut helper = *helper;
+                        let mut repetitions = Vec::new();
+                        while let Child::Rule(set, idx) = helper {
+                            let mut children = self.children(sets, set, idx);
+                            if children.is_empty() {
+                                break;
+                            }
+                            helper = children.remove(0);
+                            repetitions.push(children);
+                        }
+                        tasks.push(Task::Repetition {
+                            rule: item.rule,
+                            repetitions,
+                            base: base.to_vec(),
+                            parent,
+                        });
+                        continue;
+                    }
+                }
+                Task::Empty { rule, parent } => {
+                    let (alternative, children) =
+                        self.empty[rule].clone().expect("the rule is nullable");
+                    (rule, alternative, children, parent)
+                }
+                Task::Repetition {
+                    rule,
+                    mut repetitions,
+                    base,
+                    parent,
+                } => {
+                    let repetition = self.repetitions[rule].unwrap();
+                    match repetitions.pop() {
+                        Some(children) => {
+                            // the remaining repetitions derive the last symbol
+                            tasks.push(Task::Repetition {
+                                rule,
+                                repetitions,
+                                base,
+                                parent: Some(nodes.len()),
+                            });
+                            (rule, repetition.repeated, children, parent)
+                        }
+                        None => (rule, repetition.base, base, parent),
+                    }
+                }
+            };
+
+            let node = nodes.len();
+            let symbols = &self.grammar.rules[rule].alternatives[alternative];
+            let characters = symbols
+                .iter()
+                .zip(&children)
+                .filter_map(|(symbol, child)| match (symbol, child) {
+                    (Symbol::Range(..), Child::Text(start, end)) => {
+                        input[*start..*end].chars().next()
+                    }
+                    _ => None,
+                })
+                .collect();
+            nodes.push(ParseNode {
+                rule,
+                alternative,
+                characters,
+                parent,
+                size: 1,
+            });
+            for child in children.iter().rev() {
+                match *child {
+                    Child::Text(..) => {}
+                    Child::Rule(set, idx) => tasks.push(Task::Item {
+                        set,
+                        idx,
+                        parent: Some(node),
+                    }),
+                    Child::Empty(rule) => tasks.push(Task::Empty {
+                        rule,
+                        parent: Some(node),
+                    }),
+                }
+            }
+        }
+
+        // the parents precede their children
+        for idx in (1..nodes.len()).rev() {
+            let parent = nodes[idx].parent.unwrap();
+            nodes[parent].size += nodes[idx].size;
+        }
+        ParseTree { nodes }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use abnf::rulelist;
+
+    use super::*;
+    use crate::{
+        generator::{generate_seeds, GeneratorConfig},
+        nautilus::grammar_to_nautilus,
+        options::ConversionOptions,
+        ruleset_to_grammar,
+    };
+
+    /// Derives the text of the Nautilus rules given in pre-order.
+    fn nautilus_text(rules: &[(String, String)], ids: &mut impl Iterator<Item = usize>) -> String {
+        let (_, body) = &rules[ids.next().unwrap()];
+        let mut ret = String::new();
+        let mut parts = body.split('{');
+        ret.push_str(parts.next().unwrap());
+        for part in parts {
+            let (_, text) = part.split_once('}').unwrap();
+            ret.push_str(&nautilus_text(rules, ids));
+            ret.push_str(text);
+        }
+        ret
+    }
+
+    #[test]
+    fn parse_trees() {
+        let rules = rulelist(
+            r#"
+program = 1*stmt
+stmt = "let " name " = " expr ";" [ %x0A ] / ""
+expr = expr "+" expr / name / %x30-39
+name = %x61-7A
+"#,
+        )
+        .unwrap();
+        let grammar = ruleset_to_grammar(&rules, "program", &ConversionOptions::default()).unwrap();
+        let parser = EarleyParser::new(&grammar);
+
+        let input = "let a = 1+b;let c = 2;\n";
+        let tree = parser.parse(input).unwrap();
+        let name = |node: &ParseNode| grammar.rules[node.rule].name.as_str();
+        assert_eq!((name(&tree.nodes[0]), tree.children(0).len()), ("start", 1));
+        assert_eq!(tree.nodes[0].size, tree.nodes.len());
+        // the repetition is derived right-recursively, as in the grammar
+        let stmts = tree.nodes.iter().filter(|node| name(node) == "stmt");
+        assert_eq!(stmts.count(), 2);
+        let names = tree.nodes[1..4].iter().map(name).collect::<Vec<_>>();
+        assert_eq!(names, ["program", "at-least-1-stmt", "stmt"]);
+        let repetitions = (0..tree.nodes.len())
+            .filter(|&idx| name(&tree.nodes[idx]) == "at-least-1-stmt")
+            .map(|idx| tree.nodes[idx].parent)
+            .collect::<Vec<_>>();
+        assert_eq!(repetitions, [Some(1), Some(2)]);
+
+        // the trees derive the input from the Nautilus rules
+        let nautilus_text = |ids: &NautilusIds, tree: &NautilusTree| {
+            let nautilus = grammar_to_nautilus(ids.sampled());
+            let nautilus: Vec<(String, String)> = serde_json::from_str(&nautilus).unwrap();
+            nautilus_text(&nautilus, &mut tree.rules.iter().copied())
+        };
+        let ids = NautilusIds::new(&grammar, 256, &BTreeSet::new());
+        let nautilus_tree = ids.tree(&tree).unwrap();
+        assert_eq!(nautilus_tree.rules[0], 0);
+        assert_eq!(nautilus_tree.sizes[0], nautilus_tree.rules.len());
+        assert_eq!(nautilus_text(&ids, &nautilus_tree), input);
+        // the characters that weren't sampled have no Nautilus rules, unless they're kept
+        let ids = NautilusIds::new(&grammar, 2, &BTreeSet::new());
+        assert!(ids.tree(&parser.parse("let z = a;").unwrap()).is_some());
+        assert!(ids.tree(&tree).is_none());
+        let ids = NautilusIds::new(&grammar, 2, &input.chars().collect());
+        let nautilus_tree = ids.tree(&tree).unwrap();
+        assert_eq!(nautilus_text(&ids, &nautilus_tree), input);
+
+        let failure = parser.parse("let a = 1;\nlet b = ;").unwrap_err();
+        assert_eq!((failure.line, failure.column, failure.offset), (2, 9, 19));
+        // a digit or a name
+        assert_eq!(failure.expected, ["%x30-39", "%x61-7A"]);
+
+        let failure = parser.parse_bytes(b"let a = \xFF;").unwrap_err();
+        assert!(failure.invalid_utf8);
+        assert_eq!((failure.column, failure.expected.len()), (9, 2));
+        let failure = parser.parse_bytes(b"let 1 = \xFF;").unwrap_err();
+        assert!(!failure.invalid_utf8);
+    }
+
+    #[test]
+    fn generated_seeds_parse() {
+        let rules = rulelist(
+            r#"
+program = *( line %x0A )
+line = "let " 1*%x61-7A " = " number [ comment ]
+number = 1*DIGIT
+comment = " // " *%x20-10FFFF
+DIGIT = %x30-39
+"#,
+        )
+        .unwrap();
+        let grammar = ruleset_to_grammar(&rules, "program", &ConversionOptions::default()).unwrap();
+        let config = GeneratorConfig {
+            max_depth: 16,
+            max_length: 256,
+        };
+        let seeds = generate_seeds(&grammar, config, 100, 0);
+        assert_eq!(seeds.len(), 100);
+        assert!(seeds.iter().any(|seed| !seed.is_ascii()));
+
+        let parser = EarleyParser::new(&grammar);
+        for seed in &seeds {
+            assert!(parser.parse(seed).is_ok(), "{seed:?}");
+        }
+        assert!(parser.parse("let x = 1 // é\n").is_ok());
+
+        // the repetitions take linear time
+        let tree = parser.parse(&"let x = 1\n".repeat(20_000)).unwrap();
+        let lines = tree
+            .nodes
+            .iter()
+            .filter(|node| node.rule == tree.nodes[3].rule);
+        assert_eq!(lines.count(), 20_000);
+    }
+}
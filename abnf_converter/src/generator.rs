@@ -0,0 +1,211 @@
+use std::collections::{HashMap, HashSet};
+
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+
+use crate::{
+    analysis::{Analysis, RuleCosts},
+    grammar::{CharRange, Grammar, GrammarRule, Symbol},
+};
+
+/// The limits of a single derivation.
+#[derive(Debug, Clone, Copy)]
+pub struct GeneratorConfig {
+    /// The maximum depth of the derivation tree; the alternatives that can't be finished within
+    /// it are avoided, unless the grammar requires a deeper derivation.
+    pub max_depth: usize,
+    /// The maximum length of a derivation in bytes.
+    pub max_length: usize,
+}
+
+/// Produces random derivations from the start rule of a grammar.
+pub struct Generator<'a> {
+    rules: HashMap<&'a str, &'a GrammarRule>,
+    analysis: Analysis,
+    start: &'a str,
+    config: GeneratorConfig,
+    rng: XorShiftRng,
+}
+
+impl<'a> Generator<'a> {
+    pub fn new(grammar: &'a Grammar, config: GeneratorConfig, seed: u64) -> Self {
+        let mut rules = HashMap::new();
+        for rule in &grammar.rules {
+            rules.entry(rule.name.as_str()).or_insert(rule);
+        }
+
+        Self {
+            rules,
+            analysis: Analysis::new(grammar),
+            start: grammar.start_rule().map_or("", |r| r.name.as_str()),
+            config,
+            rng: XorShiftRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Returns a random derivation, or `None` if it exceeded the limits.
+    pub fn generate(&mut self) -> Option<String> {
+        let mut ret = String::new();
+        self.expand(self.start, 0, &mut ret).then_some(ret)
+    }
+
+    fn expand(&mut self, name: &str, depth: usize, ret: &mut String) -> bool {
+        // references to undefined rules can't be derived
+        let (Some(&rule), Some(costs)) = (self.rules.get(name), self.analysis.rule(name)) else {
+            return false;
+        };
+
+        let candidates = feasible_alternatives(costs, self.config.max_depth.saturating_sub(depth));
+        let Some(idx) = choose_alternative(&candidates, &rule.weights, &mut self.rng) else {
+            return false;
+        };
+        let alternative = &rule.alternatives[idx];
+
+        for symbol in alternative {
+            match symbol {
+                Symbol::Terminal(s) => ret.push_str(s),
+                Symbol::NonTerminal(name) => {
+                    if !self.expand(name, depth + 1, ret) {
+                        return false;
+                    }
+                }
+                Symbol::Range(start, end) => {
+                    let range = CharRange::new(*start, *end);
+                    if range.is_empty() {
+                        return false;
+                    }
+                    ret.push(range.nth(self.rng.gen_range(0..range.len())));
+                }
+            }
+            if ret.len() > self.config.max_length {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Returns the indices of the alternatives that can be finished within the depth budget, or of
+/// the shallowest ones if there are none.
+pub fn feasible_alternatives(costs: &RuleCosts, budget: usize) -> Vec<usize> {
+    let depths = costs.alternatives.iter().map(|cost| cost.min_depth);
+    let ret = depths
+        .clone()
+        .enumerate()
+        .filter(|(_, min_depth)| matches!(min_depth, Some(d) if *d <= budget))
+        .map(|(idx, _)| idx)
+        .collect::<Vec<_>>();
+    if !ret.is_empty() {
+        return ret;
+    }
+
+    let shallowest = depths.clone().flatten().min();
+    depths
+        .enumerate()
+        .filter(|(_, min_depth)| min_depth.is_some() && *min_depth == shallowest)
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+/// Picks one of the candidate alternatives according to their weights, or uniformly if the rule
+/// isn't weighted or the candidates all have a weight of zero.
+fn choose_alternative<R: Rng>(candidates: &[usize], weights: &[f64], rng: &mut R) -> Option<usize> {
+    if !weights.is_empty() {
+        if let Ok(&idx) = candidates.choose_weighted(rng, |&idx| weights[idx]) {
+            return Some(idx);
+        }
+    }
+    candidates.choose(rng).copied()
+}
+
+/// Generates up to `count` unique derivations; gives up after a number of failed or duplicate
+/// attempts, so grammars with few possible derivations may yield fewer of them.
+pub fn generate_seeds(
+    grammar: &Grammar,
+    config: GeneratorConfig,
+    count: usize,
+    seed: u64,
+) -> Vec<String> {
+    let mut generator = Generator::new(grammar, config, seed);
+    let mut seen = HashSet::new();
+    let mut ret = Vec::with_capacity(count);
+
+    let max_attempts = count.saturating_mul(10).saturating_add(100);
+    for _ in 0..max_attempts {
+        if ret.len() == count {
+            break;
+        }
+        if let Some(derivation) = generator.generate() {
+            if seen.insert(derivation.clone()) {
+                ret.push(derivation);
+            }
+        }
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use abnf::rulelist;
+
+    use super::*;
+    use crate::{
+        options::ConversionOptions,
+        ruleset_to_grammar,
+        weights::{AlternativeWeights, RuleWeights},
+    };
+
+    #[test]
+    fn random_derivations() {
+        let rules = rulelist("expr = \"x\" / \"(\" expr \")\" / 1*2%x61-63\n").unwrap();
+        let grammar = ruleset_to_grammar(&rules, "expr", &ConversionOptions::default()).unwrap();
+        let config = GeneratorConfig {
+            max_depth: 8,
+            max_length: 16,
+        };
+
+        let seeds = generate_seeds(&grammar, config, 20, 0);
+        assert_eq!(seeds.len(), 20);
+        assert_eq!(seeds, generate_seeds(&grammar, config, 20, 0));
+        for seed in &seeds {
+            assert!(seed.len() <= config.max_length);
+            let inner = seed.trim_start_matches('(');
+            let depth = seed.len() - inner.len();
+            let inner = inner.strip_suffix(&")".repeat(depth)).unwrap();
+            assert!(inner == "x" || (1..=2).contains(&inner.len()), "{seed}");
+            assert!(inner.chars().all(|c| matches!(c, 'x' | 'a'..='c')));
+        }
+
+        // there are only three possible derivations
+        let rules = rulelist("a = \"x\" / \"y\" / \"z\"\n").unwrap();
+        let grammar = ruleset_to_grammar(&rules, "a", &ConversionOptions::default()).unwrap();
+        assert_eq!(generate_seeds(&grammar, config, 10, 1).len(), 3);
+
+        // the alternatives with a weight of zero are never picked
+        let weights = RuleWeights::from_json(r#"{"a": [0, 1, 3]}"#).unwrap();
+        let grammar = ruleset_to_grammar(
+            &rules,
+            "a",
+            &ConversionOptions {
+                weights: AlternativeWeights::new(&rules, &weights).unwrap(),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut seeds = generate_seeds(&grammar, config, 10, 1);
+        seeds.sort();
+        assert_eq!(seeds, ["y", "z"]);
+
+        // the derivations begin with a start rule defined in the grammar, even if it's not the
+        // first rule
+        let rules = rulelist("start = \"(\" x \")\"\nx = 2*3y\ny = \"a\"\n").unwrap();
+        let mut grammar =
+            ruleset_to_grammar(&rules, "start", &ConversionOptions::default()).unwrap();
+        grammar.rules.rotate_left(1);
+        let mut seeds = generate_seeds(&grammar, config, 10, 0);
+        seeds.sort();
+        assert_eq!(seeds, ["(aa)", "(aaa)"]);
+    }
+}
@@ -0,0 +1,518 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    io, iter,
+};
+
+use abnf::types::{Node, TerminalValues};
+use serde::ser::{Error, Serialize, SerializeMap, SerializeSeq, Serializer};
+use serde_json::ser::Formatter;
+
+use crate::{json_rule_name_from_group, START_RULE};
+
+/// A single element of a grammar alternative.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Symbol {
+    Terminal(String),
+    NonTerminal(String),
+    /// A single character from an inclusive range of code points.
+    Range(u32, u32),
+}
+
+impl Symbol {
+    /// The Fuzzingbook-style representation of the symbol, i.e. `<name>` for non-terminals;
+    /// ranges don't have one, they need to be sampled first.
+    pub fn to_json_string(&self) -> Option<String> {
+        match self {
+            Self::Terminal(s) => Some(s.clone()),
+            Self::NonTerminal(name) => Some(json_key(name)),
+            Self::Range(..) => None,
+        }
+    }
+}
+
+/// A sequence of symbols that a rule can expand to.
+pub type Alternative = Vec<Symbol>;
+
+/// The part of the ABNF grammar a rule was created for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RuleOrigin {
+    /// A rule defined in the ABNF grammar.
+    #[default]
+    Rule,
+    /// The start symbol expected by the grammar-based fuzzers.
+    Start,
+    /// A repetition; `max` is `None` for the unbounded ones, which reference themselves.
+    Repetition { min: usize, max: Option<usize> },
+    /// A nested group or option.
+    Group,
+    /// A range of code points.
+    Range(u32, u32),
+    /// Both cases of an ASCII letter of a case-insensitive string.
+    CaseInsensitiveLetter,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GrammarRule {
+    pub name: String,
+    pub alternatives: Vec<Alternative>,
+    pub origin: RuleOrigin,
+    /// The relative weights of the alternatives; empty if they're equally likely.
+    pub weights: Vec<f64>,
+}
+
+impl GrammarRule {
+    /// The probabilities of the alternatives, if they're weighted.
+    pub fn probabilities(&self) -> Option<Vec<f64>> {
+        let total = self.weights.iter().sum::<f64>();
+        (!self.weights.is_empty()).then(|| self.weights.iter().map(|w| w / total).collect())
+    }
+}
+
+/// An ordered list of rules, the first one being the `<start>` rule that the derivations begin
+/// with; see [`Grammar::start_rule`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Grammar {
+    pub rules: Vec<GrammarRule>,
+    /// The start rule of the ABNF grammar.
+    pub start: String,
+}
+
+impl Grammar {
+    /// The rule that the derivations begin with: the `<start>` rule, or the first rule of the
+    /// grammars that don't have one.
+    pub fn start_rule(&self) -> Option<&GrammarRule> {
+        self.rules
+            .iter()
+            .find(|rule| rule.name == START_RULE)
+            .or(self.rules.first())
+    }
+
+    /// Serializes the grammar as Fuzzingbook-style JSON; the ranges need to be sampled first.
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(try_to_json_lines(self)?)
+    }
+
+    /// Like [`Grammar::to_json`], but the alternatives of the weighted rules are annotated with
+    /// their probabilities, which not every consumer of the JSON grammars accepts.
+    pub fn to_json_with_probabilities(&self) -> anyhow::Result<String> {
+        Ok(try_to_json_lines(&JsonGrammar {
+            grammar: self,
+            probabilities: true,
+        })?)
+    }
+
+    /// Replaces character ranges with sets of at most `max_range_size` terminals, for the
+    /// formats that can't express ranges directly.
+    pub fn sample_ranges(&self, max_range_size: usize) -> Grammar {
+        self.sample_ranges_keeping(max_range_size, &BTreeSet::new())
+    }
+
+    /// Samples the ranges like [`Grammar::sample_ranges`], but also keeps the given characters in
+    /// the ranges that contain them, e.g. the ones of the inputs whose derivations refer to the
+    /// sampled grammar.
+    pub fn sample_ranges_keeping(&self, max_range_size: usize, kept: &BTreeSet<char>) -> Grammar {
+        let mut ret = Grammar {
+            start: self.start.clone(),
+            ..Default::default()
+        };
+        let mut range_rules = Vec::new();
+
+        for rule in &self.rules {
+            let mut alternatives = Vec::with_capacity(rule.alternatives.len());
+            let mut weights = Vec::with_capacity(rule.weights.len());
+            for (idx, alternative) in rule.alternatives.iter().enumerate() {
+                let count_before = alternatives.len();
+                match alternative.as_slice() {
+                    [Symbol::Range(start, end)] => {
+                        alternatives.extend(
+                            sample_range_keeping(*start, *end, max_range_size, kept)
+                                .into_iter()
+                                .map(|c| vec![Symbol::Terminal(c.to_string())]),
+                        );
+                    }
+                    symbols => {
+                        // ranges that aren't the sole symbol need a rule of their own
+                        let symbols = symbols
+                            .iter()
+                            .map(|symbol| match symbol {
+                                Symbol::Range(start, end) => {
+                                    range_rules.push((*start, *end));
+                                    Symbol::NonTerminal(range_rule_name(*start, *end))
+                                }
+                                symbol => symbol.clone(),
+                            })
+                            .collect();
+                        alternatives.push(symbols);
+                    }
+                }
+                // the sampled characters share the weight of the range
+                if let Some(weight) = rule.weights.get(idx) {
+                    let count = alternatives.len() - count_before;
+                    weights.extend(iter::repeat_n(weight / count as f64, count));
+                }
+            }
+            ret.rules.push(GrammarRule {
+                name: rule.name.clone(),
+                alternatives,
+                origin: rule.origin,
+                weights,
+            });
+        }
+
+        let mut known_rule_names = ret
+            .rules
+            .iter()
+            .map(|rule| rule.name.clone())
+            .collect::<HashSet<_>>();
+        for (start, end) in range_rules {
+            let name = range_rule_name(start, end);
+            if known_rule_names.insert(name.clone()) {
+                ret.rules.push(GrammarRule {
+                    name,
+                    alternatives: sample_range_keeping(start, end, max_range_size, kept)
+                        .into_iter()
+                        .map(|c| vec![Symbol::Terminal(c.to_string())])
+                        .collect(),
+                    origin: RuleOrigin::Range(start, end),
+                    ..Default::default()
+                });
+            }
+        }
+
+        ret
+    }
+}
+
+fn range_rule_name(start: u32, end: u32) -> String {
+    json_rule_name_from_group(
+        &Node::TerminalValues(TerminalValues::Range(start, end)),
+        true,
+    )
+}
+
+/// The valid characters of an inclusive range of code points.
+pub struct CharRange {
+    segments: Vec<(u32, u32)>,
+}
+
+impl CharRange {
+    pub fn new(start: u32, end: u32) -> Self {
+        // the surrogates are not valid characters
+        const SURROGATES: (u32, u32) = (0xD800, 0xDFFF);
+
+        let end = end.min(char::MAX as u32);
+        let mut segments = Vec::with_capacity(2);
+        if start < SURROGATES.0 {
+            segments.push((start, end.min(SURROGATES.0 - 1)));
+        }
+        if end > SURROGATES.1 {
+            segments.push((start.max(SURROGATES.1 + 1), end));
+        }
+        segments.retain(|(start, end)| start <= end);
+
+        Self { segments }
+    }
+
+    pub fn len(&self) -> usize {
+        self.segments
+            .iter()
+            .map(|(start, end)| (end - start) as usize + 1)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    pub fn nth(&self, mut idx: usize) -> char {
+        for (start, end) in &self.segments {
+            let len = (end - start) as usize + 1;
+            if idx < len {
+                return char::from_u32(start + idx as u32).unwrap();
+            }
+            idx -= len;
+        }
+        panic!("the index is out of the range's bounds");
+    }
+}
+
+/// Returns the valid characters from the range if there are at most `max_size` of them, or
+/// `max_size` evenly spaced characters including both ends of the range otherwise.
+pub fn sample_range(start: u32, end: u32, max_size: usize) -> Vec<char> {
+    let range = CharRange::new(start, end);
+    let size = range.len();
+    let nth = |idx| range.nth(idx);
+
+    if size <= max_size {
+        (0..size).map(nth).collect()
+    } else if max_size == 1 {
+        vec![nth(0)]
+    } else {
+        (0..max_size)
+            .map(|i| nth(i * (size - 1) / (max_size - 1)))
+            .collect()
+    }
+}
+
+/// Samples the range like [`sample_range`], adding the given characters that are in the range.
+pub fn sample_range_keeping(
+    start: u32,
+    end: u32,
+    max_size: usize,
+    kept: &BTreeSet<char>,
+) -> Vec<char> {
+    let mut ret = sample_range(start, end, max_size);
+    ret.extend(
+        kept.iter()
+            .filter(|&&c| (start..=end).contains(&(c as u32))),
+    );
+    ret.sort_unstable();
+    ret.dedup();
+    ret
+}
+
+/// Serializes the value as JSON with every element of the top-level container in a separate line.
+pub fn to_json_lines<T: Serialize>(value: &T) -> String {
+    // serializing strings and sequences can't fail
+    try_to_json_lines(value).unwrap()
+}
+
+fn try_to_json_lines<T: Serialize>(value: &T) -> serde_json::Result<String> {
+    let mut serializer =
+        serde_json::Serializer::with_formatter(Vec::new(), LineFormatter { depth: 0 });
+    value.serialize(&mut serializer)?;
+    Ok(String::from_utf8(serializer.into_inner()).unwrap())
+}
+
+struct LineFormatter {
+    depth: usize,
+}
+
+impl LineFormatter {
+    fn begin_value<W: ?Sized + io::Write>(&self, writer: &mut W, first: bool) -> io::Result<()> {
+        match (self.depth, first) {
+            (1, true) => writer.write_all(b"\n  "),
+            (1, false) => writer.write_all(b",\n  "),
+            (_, true) => Ok(()),
+            (_, false) => writer.write_all(b", "),
+        }
+    }
+
+    fn end_container<W: ?Sized + io::Write>(
+        &mut self,
+        writer: &mut W,
+        bracket: &[u8],
+    ) -> io::Result<()> {
+        self.depth -= 1;
+        if self.depth == 0 {
+            writer.write_all(b"\n")?;
+        }
+        writer.write_all(bracket)
+    }
+}
+
+impl Formatter for LineFormatter {
+    fn begin_array<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.depth += 1;
+        writer.write_all(b"[")
+    }
+
+    fn end_array<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.end_container(writer, b"]")
+    }
+
+    fn begin_array_value<W: ?Sized + io::Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        self.begin_value(writer, first)
+    }
+
+    fn begin_object<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.depth += 1;
+        writer.write_all(b"{")
+    }
+
+    fn end_object<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.end_container(writer, b"}")
+    }
+
+    fn begin_object_key<W: ?Sized + io::Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        self.begin_value(writer, first)
+    }
+
+    fn begin_object_value<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(b": ")
+    }
+}
+
+pub fn json_key(name: &str) -> String {
+    format!("<{name}>")
+}
+
+struct JsonAlternative<'a>(&'a Alternative);
+
+impl Serialize for JsonAlternative<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for symbol in self.0 {
+            let symbol = symbol.to_json_string().ok_or_else(|| {
+                S::Error::custom("the ranges need to be sampled before the serialization")
+            })?;
+            seq.serialize_element(&symbol)?;
+        }
+        seq.end()
+    }
+}
+
+/// An alternative with the Fuzzingbook-style probability annotation, i.e. the
+/// `[expansion, {"prob": p}]` pair.
+struct JsonWeightedAlternative<'a>(&'a Alternative, f64);
+
+impl Serialize for JsonWeightedAlternative<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(2))?;
+        seq.serialize_element(&JsonAlternative(self.0))?;
+        seq.serialize_element(&JsonProbability(self.1))?;
+        seq.end()
+    }
+}
+
+struct JsonProbability(f64);
+
+impl Serialize for JsonProbability {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry("prob", &self.0)?;
+        map.end()
+    }
+}
+
+struct JsonAlternatives<'a> {
+    rule: &'a GrammarRule,
+    probabilities: bool,
+}
+
+impl Serialize for JsonAlternatives<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let alternatives = &self.rule.alternatives;
+        let mut seq = serializer.serialize_seq(Some(alternatives.len()))?;
+        match self.rule.probabilities().filter(|_| self.probabilities) {
+            Some(probabilities) => {
+                for (alternative, probability) in alternatives.iter().zip(probabilities) {
+                    seq.serialize_element(&JsonWeightedAlternative(alternative, probability))?;
+                }
+            }
+            None => {
+                for alternative in alternatives {
+                    seq.serialize_element(&JsonAlternative(alternative))?;
+                }
+            }
+        }
+        seq.end()
+    }
+}
+
+struct JsonGrammar<'a> {
+    grammar: &'a Grammar,
+    probabilities: bool,
+}
+
+impl Serialize for JsonGrammar<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let rules = &self.grammar.rules;
+        let mut map = serializer.serialize_map(Some(rules.len()))?;
+        for rule in rules {
+            let alternatives = JsonAlternatives {
+                rule,
+                probabilities: self.probabilities,
+            };
+            map.serialize_entry(&json_key(&rule.name), &alternatives)?;
+        }
+        map.end()
+    }
+}
+
+impl Serialize for Grammar {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        JsonGrammar {
+            grammar: self,
+            probabilities: false,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_sampling() {
+        assert_eq!(sample_range(0x61, 0x63, 256), ['a', 'b', 'c']);
+        assert_eq!(sample_range(0x61, 0x65, 3), ['a', 'c', 'e']);
+
+        let sampled = sample_range(0x20, 0x10FFFF, 256);
+        assert_eq!(sampled.len(), 256);
+        assert_eq!(sampled.first(), Some(&' '));
+        assert_eq!(sampled.last(), Some(&'\u{10FFFF}'));
+        assert!(sampled.windows(2).all(|w| w[0] < w[1]));
+
+        // the surrogates are skipped
+        assert_eq!(sample_range(0xD7FF, 0xE000, 256), ['\u{D7FF}', '\u{E000}']);
+
+        let kept = BTreeSet::from(['b', 'd', 'z']);
+        assert_eq!(
+            sample_range_keeping(0x61, 0x65, 3, &kept),
+            ['a', 'b', 'c', 'd', 'e']
+        );
+    }
+
+    #[test]
+    fn ranges_in_sequences() {
+        let grammar = Grammar {
+            rules: vec![GrammarRule {
+                name: "a".into(),
+                alternatives: vec![
+                    vec![Symbol::Range(0x61, 0x62)],
+                    vec![Symbol::Terminal("x".into()), Symbol::Range(0x61, 0x62)],
+                ],
+                origin: RuleOrigin::Rule,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let sampled = grammar.sample_ranges(1);
+        let t = |s: &str| vec![Symbol::Terminal(s.into())];
+        assert_eq!(sampled.rules[0].alternatives[0], t("a"));
+        assert_eq!(
+            sampled.rules[0].alternatives[1],
+            [
+                Symbol::Terminal("x".into()),
+                Symbol::NonTerminal("b97-to-b98".into())
+            ]
+        );
+        assert_eq!(sampled.rules[1].name, "b97-to-b98");
+        assert_eq!(sampled.rules[1].alternatives, [t("a")]);
+        assert_eq!(sampled.rules[1].origin, RuleOrigin::Range(0x61, 0x62));
+
+        // the sampled characters share the weight of the range
+        let mut grammar = grammar;
+        grammar.rules[0].weights = vec![3.0, 1.0];
+        let sampled = grammar.sample_ranges(2);
+        assert_eq!(sampled.rules[0].weights, [1.5, 1.5, 1.0]);
+        assert!(sampled.rules[1].weights.is_empty());
+        assert!(grammar.to_json().is_err());
+        assert!(!sampled.to_json().unwrap().contains("prob"));
+        assert!(sampled
+            .to_json_with_probabilities()
+            .unwrap()
+            .contains(r#"[[["a"], {"prob": 0.375}], [["b"], {"prob": 0.375}], [["x", "<b97-to-b98>"], {"prob": 0.25}]]"#));
+    }
+}
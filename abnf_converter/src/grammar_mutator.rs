@@ -0,0 +1,272 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use crate::{
+    grammar::{Grammar, GrammarRule, Symbol},
+    validation::Severity,
+    START_RULE,
+};
+
+/// A Grammar-Mutator constraint that the grammar doesn't satisfy; the warnings were fixed while
+/// converting it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    MissingStart,
+    /// Rule names end up in C identifiers, with dashes replaced by underscores.
+    Renamed {
+        rule: String,
+        name: String,
+    },
+    /// Terminals starting with something like `<name>` would be taken for non-terminals.
+    SplitTerminal {
+        rule: String,
+        terminal: String,
+    },
+    Undefined {
+        rule: String,
+        reference: String,
+    },
+    /// The terminals are handled as C strings.
+    NulByte {
+        rule: String,
+    },
+}
+
+impl Violation {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::Renamed { .. } | Self::SplitTerminal { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.severity())?;
+        match self {
+            Self::MissingStart => write!(f, "the grammar has no `<{START_RULE}>` rule"),
+            Self::Renamed { rule, name } => {
+                write!(f, "rule `{rule}` was renamed to `{name}`")
+            }
+            Self::SplitTerminal { rule, terminal } => {
+                write!(
+                    f,
+                    "rule `{rule}` has the terminal {terminal:?} that looks like a non-terminal; \
+                     it was split in two"
+                )
+            }
+            Self::Undefined { rule, reference } => {
+                write!(
+                    f,
+                    "rule `{rule}` references the undefined rule `{reference}`"
+                )
+            }
+            Self::NulByte { rule } => write!(f, "rule `{rule}` has a terminal with a NUL byte"),
+        }
+    }
+}
+
+/// Grammar-Mutator treats every string that starts with `<`, followed by anything but spaces and
+/// angle brackets, and a `>`, as a non-terminal.
+fn looks_like_non_terminal(s: &str) -> bool {
+    s.strip_prefix('<')
+        .and_then(|rest| rest.find(['<', '>', ' ']))
+        .is_some_and(|idx| s.as_bytes()[idx + 1] == b'>')
+}
+
+/// Maps the rule names to ones that are unique even with the dashes replaced with underscores.
+fn rule_names(grammar: &Grammar, violations: &mut Vec<Violation>) -> HashMap<String, String> {
+    let mut names = HashMap::new();
+    let mut taken = HashSet::new();
+
+    for rule in &grammar.rules {
+        if names.contains_key(&rule.name) {
+            continue;
+        }
+
+        let name = rule
+            .name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        let mut unique_name = name.clone();
+        let mut idx = 2;
+        while taken.contains(&unique_name.replace('-', "_")) {
+            unique_name = format!("{name}_{idx}");
+            idx += 1;
+        }
+        taken.insert(unique_name.replace('-', "_"));
+
+        if unique_name != rule.name {
+            violations.push(Violation::Renamed {
+                rule: rule.name.clone(),
+                name: unique_name.clone(),
+            });
+        }
+        names.insert(rule.name.clone(), unique_name);
+    }
+
+    names
+}
+
+/// Adjusts the grammar (with its ranges already sampled) to the format expected by
+/// Grammar-Mutator, and reports the constraints it violates.
+pub fn grammar_mutator_profile(grammar: &Grammar) -> (Grammar, Vec<Violation>) {
+    let mut violations = Vec::new();
+    if !grammar.rules.iter().any(|rule| rule.name == START_RULE) {
+        violations.push(Violation::MissingStart);
+    }
+    let names = rule_names(grammar, &mut violations);
+
+    let mut ret = Grammar {
+        start: grammar.start.clone(),
+        ..Default::default()
+    };
+    let mut emitted = HashSet::new();
+    for rule in &grammar.rules {
+        if !emitted.insert(&rule.name) {
+            continue;
+        }
+
+        let mut alternatives = Vec::with_capacity(rule.alternatives.len());
+        let mut has_nul = false;
+        for alternative in &rule.alternatives {
+            let mut symbols = Vec::with_capacity(alternative.len());
+            for symbol in alternative {
+                if let Symbol::Terminal(s) = symbol {
+                    has_nul |= s.contains('\0');
+                }
+                match symbol {
+                    Symbol::Terminal(s) if looks_like_non_terminal(s) => {
+                        violations.push(Violation::SplitTerminal {
+                            rule: rule.name.clone(),
+                            terminal: s.clone(),
+                        });
+                        symbols.push(Symbol::Terminal("<".into()));
+                        symbols.push(Symbol::Terminal(s[1..].to_owned()));
+                    }
+                    Symbol::Terminal(_) => symbols.push(symbol.clone()),
+                    Symbol::NonTerminal(nt) => match names.get(nt) {
+                        Some(name) => symbols.push(Symbol::NonTerminal(name.clone())),
+                        None => {
+                            violations.push(Violation::Undefined {
+                                rule: rule.name.clone(),
+                                reference: nt.clone(),
+                            });
+                            symbols.push(symbol.clone());
+                        }
+                    },
+                    Symbol::Range(..) => {
+                        unreachable!("ranges need to be sampled before serialization")
+                    }
+                }
+            }
+            alternatives.push(symbols);
+        }
+        if has_nul {
+            violations.push(Violation::NulByte {
+                rule: rule.name.clone(),
+            });
+        }
+
+        ret.rules.push(GrammarRule {
+            name: names[&rule.name].clone(),
+            alternatives,
+            origin: rule.origin,
+            // Grammar-Mutator picks the alternatives uniformly
+            ..Default::default()
+        });
+    }
+
+    (ret, violations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grammar_mutator_constraints() {
+        let t = |s: &str| Symbol::Terminal(s.into());
+        let nt = |s: &str| Symbol::NonTerminal(s.into());
+        let grammar = Grammar {
+            rules: vec![
+                GrammarRule {
+                    name: "program".into(),
+                    alternatives: vec![vec![nt("a-b"), nt("a_b"), nt("（x）")], vec![t("<=")]],
+                    ..Default::default()
+                },
+                GrammarRule {
+                    name: "a-b".into(),
+                    alternatives: vec![vec![t("<a>"), t("a\0"), nt("missing")]],
+                    ..Default::default()
+                },
+                GrammarRule {
+                    name: "a_b".into(),
+                    alternatives: vec![vec![t("<a b>")]],
+                    ..Default::default()
+                },
+                GrammarRule {
+                    name: "（x）".into(),
+                    alternatives: vec![vec![t("<>")]],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let (converted, violations) = grammar_mutator_profile(&grammar);
+        assert_eq!(
+            converted.rules[0].alternatives[0],
+            [nt("a-b"), nt("a_b_2"), nt("_x_")]
+        );
+        assert_eq!(converted.rules[0].alternatives[1], [t("<=")]);
+        assert_eq!(
+            converted.rules[1].alternatives[0],
+            [t("<"), t("a>"), t("a\0"), nt("missing")]
+        );
+        assert_eq!(converted.rules[2].alternatives[0], [t("<a b>")]);
+        assert_eq!(converted.rules[3].alternatives[0], [t("<"), t(">")]);
+
+        assert_eq!(
+            violations,
+            [
+                Violation::MissingStart,
+                Violation::Renamed {
+                    rule: "a_b".into(),
+                    name: "a_b_2".into()
+                },
+                Violation::Renamed {
+                    rule: "（x）".into(),
+                    name: "_x_".into()
+                },
+                Violation::SplitTerminal {
+                    rule: "a-b".into(),
+                    terminal: "<a>".into()
+                },
+                Violation::Undefined {
+                    rule: "a-b".into(),
+                    reference: "missing".into()
+                },
+                Violation::NulByte { rule: "a-b".into() },
+                Violation::SplitTerminal {
+                    rule: "（x）".into(),
+                    terminal: "<>".into()
+                },
+            ]
+        );
+        assert_eq!(
+            violations[1].to_string(),
+            "warning: rule `a_b` was renamed to `a_b_2`"
+        );
+    }
+}
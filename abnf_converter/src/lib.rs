@@ -0,0 +1,815 @@
+pub mod analysis;
+pub mod antlr;
+pub mod core_rules;
+pub mod coverage;
+pub mod dictionary;
+pub mod diff;
+pub mod earley;
+pub mod generator;
+pub mod grammar;
+pub mod grammar_mutator;
+pub mod naming;
+pub mod nautilus;
+pub mod options;
+pub mod preprocess;
+pub mod serialize;
+pub mod simplify;
+pub mod subset;
+pub mod syntax;
+pub mod validation;
+pub mod weights;
+pub mod whitespace;
+
+use std::{collections::HashSet, iter};
+
+use abnf::types::{Node, Repeat, Rule, TerminalValues};
+use anyhow::{anyhow, bail, Context};
+use grammar::{Alternative, Grammar, GrammarRule, RuleOrigin, Symbol};
+use naming::NestedRuleNames;
+use options::{CaseMode, ConversionOptions};
+use weights::top_level_alternatives;
+
+const NESTED_RULE_START: char = '（';
+const NESTED_RULE_END: char = '）';
+pub const START_RULE: &str = "start";
+
+/// The nested groups, options, repetitions and ranges of the rules, which become rules of their
+/// own, along with the names of the rules they're extracted from.
+fn extract_nested_nodes(rules: &[Rule]) -> Vec<(Node, &str)> {
+    let mut ret = Vec::new();
+    for rule in rules {
+        let mut nodes = Vec::new();
+        extract_nested_nodes_from_node(rule.node(), &mut nodes);
+        ret.extend(nodes.into_iter().map(|node| (node, rule.name())));
+    }
+    ret
+}
+
+fn extract_nested_nodes_from_node(node: &Node, ret: &mut Vec<Node>) {
+    match node {
+        Node::Alternatives(nodes) | Node::Concatenation(nodes) => {
+            nodes
+                .iter()
+                .for_each(|n| extract_nested_nodes_from_node(n, ret));
+        }
+        Node::Repetition { repeat: _, node: n } => {
+            ret.push(node.clone());
+            extract_nested_nodes_from_node(n, ret);
+        }
+        Node::Group(n) => {
+            ret.push(node.clone());
+            extract_nested_nodes_from_node(n, ret);
+        }
+        Node::Optional(n) => {
+            ret.push(node.clone());
+            extract_nested_nodes_from_node(n, ret);
+        }
+        Node::TerminalValues(TerminalValues::Range(..)) => {
+            ret.push(node.clone());
+        }
+        _ => {}
+    }
+}
+
+fn repetition_rule_name(node: &Node, toplevel: bool) -> String {
+    let Node::Repetition { repeat, node } = node else {
+        unreachable!();
+    };
+
+    let mut plural = true;
+    let prefix = match repeat {
+        Repeat::Specific(n) => {
+            format!("{n}")
+        }
+        Repeat::Variable { min, max } => {
+            if let (Some(min), Some(max)) = (min, max) {
+                format!("between-{min}-and-{max}")
+            } else if let Some(min) = min {
+                if *min == 1 {
+                    plural = false;
+                }
+                format!("at-least-{min}")
+            } else if let Some(max) = max {
+                if *max == 1 {
+                    plural = false;
+                }
+                format!("at-most-{max}")
+            } else {
+                "zero-or-more".to_string()
+            }
+        }
+    };
+    let rule_name = json_rule_name_from_group(node, toplevel);
+    if matches!(&**node, Node::Group(..) | Node::Repetition { .. }) {
+        plural = false;
+    }
+
+    format!("{prefix}-{rule_name}{}", if plural { "s" } else { "" })
+}
+
+pub fn json_rule_name_from_group(node: &Node, toplevel: bool) -> String {
+    let mut ret = String::new();
+    match node {
+        Node::Alternatives(nodes) => {
+            if !toplevel {
+                ret.push(NESTED_RULE_START);
+            }
+            let mut node_iter = nodes.iter().peekable();
+            while let Some(node) = node_iter.next() {
+                let name = json_rule_name_from_group(node, false);
+                ret.push_str(&name);
+                if node_iter.peek().is_some() {
+                    ret.push_str("-or-");
+                }
+            }
+            if !toplevel {
+                ret.push(NESTED_RULE_END);
+            }
+        }
+        Node::Concatenation(nodes) => {
+            if !toplevel {
+                ret.push(NESTED_RULE_START);
+            }
+            let mut node_iter = nodes.iter().peekable();
+            while let Some(node) = node_iter.next() {
+                let name = json_rule_name_from_group(node, false);
+                ret.push_str(&name);
+                if node_iter.peek().is_some() {
+                    ret.push_str("-and-");
+                }
+            }
+            if !toplevel {
+                ret.push(NESTED_RULE_END);
+            }
+        }
+        node @ Node::Repetition { .. } => {
+            if !toplevel {
+                ret.push(NESTED_RULE_START);
+            }
+            ret.push_str(&repetition_rule_name(node, false));
+            if !toplevel {
+                ret.push(NESTED_RULE_END);
+            }
+        }
+        Node::Rulename(rule) => {
+            ret.push_str(rule);
+        }
+        Node::Group(node) => {
+            ret.push_str(&json_rule_name_from_group(node, toplevel));
+        }
+        Node::Optional(node) => {
+            ret.push_str(&format!(
+                "optional-{}",
+                json_rule_name_from_group(node, false)
+            ));
+        }
+        Node::String(s) => {
+            ret.push_str(s.as_str());
+        }
+        Node::TerminalValues(tv) => match tv {
+            TerminalValues::Range(start, end) => {
+                if !toplevel {
+                    ret.push(NESTED_RULE_START);
+                }
+                ret.push_str(&format!("b{start}-to-b{end}"));
+                if !toplevel {
+                    ret.push(NESTED_RULE_END);
+                }
+            }
+            TerminalValues::Concatenation(cs) => {
+                if !toplevel {
+                    ret.push(NESTED_RULE_START);
+                }
+                let mut val_iter = cs.iter().copied().peekable();
+                while let Some(val) = val_iter.next() {
+                    // invalid code points are reported when the rule is converted
+                    let s = match char::from_u32(val) {
+                        Some(c) => format!("{:?}", c.to_string()),
+                        None => format!("x{val:X}"),
+                    };
+                    ret.push_str(&s);
+
+                    if val_iter.peek().is_some() {
+                        ret.push_str("-and-");
+                    }
+                }
+                if !toplevel {
+                    ret.push(NESTED_RULE_END);
+                }
+            }
+        },
+        Node::Prose(prose) => {
+            ret.push_str("prose-");
+            ret.push_str(prose);
+        }
+    }
+
+    ret.retain(|c| c != '.');
+    let ret = ret.replace("_", "underscore");
+    ret.replace("--", "-minus")
+}
+
+/// Concatenates every alternative in `prefixes` with every alternative in `suffixes`, with the
+/// separator in between if there is one.
+fn concatenate_alternatives(
+    prefixes: &[Alternative],
+    suffixes: &[Alternative],
+    separator: Option<&Symbol>,
+) -> Vec<Alternative> {
+    let mut ret = Vec::with_capacity(prefixes.len() * suffixes.len());
+    for prefix in prefixes {
+        for suffix in suffixes {
+            let mut alternative = prefix.clone();
+            if let Some(separator) = separator {
+                if whitespace::needs_separator(prefix, suffix, separator) {
+                    alternative.push(separator.clone());
+                }
+            }
+            alternative.extend(suffix.iter().cloned());
+            ret.push(alternative);
+        }
+    }
+    ret
+}
+
+fn repeat_alternatives(
+    single: &[Alternative],
+    count: usize,
+    separator: Option<&Symbol>,
+) -> Vec<Alternative> {
+    (0..count).fold(vec![vec![]], |acc, _| {
+        concatenate_alternatives(&acc, single, separator)
+    })
+}
+
+fn json_rule_body_from_group(
+    main_node: &Node,
+    names: &NestedRuleNames,
+    toplevel: bool,
+    case_mode: CaseMode,
+    separator: Option<&Symbol>,
+) -> anyhow::Result<Vec<Alternative>> {
+    if !toplevel {
+        if let Some(name) = names.get(main_node) {
+            return Ok(vec![vec![Symbol::NonTerminal(name.to_owned())]]);
+        }
+    }
+
+    let ret = match main_node {
+        Node::Alternatives(nodes) => {
+            let mut ret = Vec::new();
+            for node in nodes {
+                ret.extend(json_rule_body_from_group(
+                    node, names, false, case_mode, separator,
+                )?);
+            }
+            ret
+        }
+        Node::Concatenation(nodes) => {
+            let mut ret = vec![vec![]];
+            for node in nodes {
+                let body = json_rule_body_from_group(node, names, false, case_mode, separator)?;
+                ret = concatenate_alternatives(&ret, &body, separator);
+            }
+            ret
+        }
+        Node::Repetition { repeat, node } => {
+            let single = json_rule_body_from_group(node, names, false, case_mode, separator)?;
+            let (min, max) = match repeat {
+                Repeat::Specific(n) => (*n, Some(*n)),
+                Repeat::Variable { min, max } => (min.unwrap_or(0), *max),
+            };
+
+            if let Some(max) = max {
+                (min..=max)
+                    .flat_map(|count| repeat_alternatives(&single, count, separator))
+                    .collect()
+            } else {
+                // an unbounded repetition is either the minimum number
+                // of elements, or one more element followed by itself
+                let name = names
+                    .get(main_node)
+                    .map(|name| name.to_owned())
+                    .unwrap_or_else(|| json_rule_name_from_group(main_node, true));
+                let more = vec![vec![Symbol::NonTerminal(name)]];
+                let mut ret = repeat_alternatives(&single, min, separator);
+                ret.extend(concatenate_alternatives(&single, &more, separator));
+                ret
+            }
+        }
+        Node::Rulename(rule) => vec![vec![Symbol::NonTerminal(rule.clone())]],
+        Node::Group(node) => json_rule_body_from_group(node, names, false, case_mode, separator)?,
+        Node::Optional(node) => {
+            let mut ret = vec![vec![]];
+            ret.extend(json_rule_body_from_group(
+                node, names, false, case_mode, separator,
+            )?);
+            ret
+        }
+        Node::String(s) if case_mode == CaseMode::Expand && !s.is_case_sensitive() => {
+            vec![case_insensitive_symbols(s.as_str(), names)]
+        }
+        Node::String(s) => vec![vec![Symbol::Terminal(s.as_str().to_owned())]],
+        Node::TerminalValues(tv) => match tv {
+            TerminalValues::Range(start, end) => vec![vec![Symbol::Range(*start, *end)]],
+            TerminalValues::Concatenation(cs) => vec![vec![Symbol::Terminal(terminal_string(cs)?)]],
+        },
+        Node::Prose(prose) => bail!("the prose value `<{prose}>` needs to be replaced first"),
+    };
+    Ok(ret)
+}
+
+/// Converts a `%x41.42`-style sequence of code points to a string.
+pub fn terminal_string(code_points: &[u32]) -> anyhow::Result<String> {
+    code_points
+        .iter()
+        .map(|&val| {
+            char::from_u32(val).ok_or_else(|| anyhow!("`%x{val:X}` is not a valid character"))
+        })
+        .collect()
+}
+
+/// Splits a case-insensitive string into terminals and references to rules covering both cases
+/// of its letters.
+fn case_insensitive_symbols(s: &str, names: &NestedRuleNames) -> Alternative {
+    let mut ret = Vec::new();
+    let mut terminal = String::new();
+    for c in s.chars() {
+        if c.is_ascii_alphabetic() {
+            if !terminal.is_empty() {
+                ret.push(Symbol::Terminal(std::mem::take(&mut terminal)));
+            }
+            let name = match names.letter(c) {
+                Some(name) => name.to_owned(),
+                None => format!("{}-or-{}", c.to_ascii_lowercase(), c.to_ascii_uppercase()),
+            };
+            ret.push(Symbol::NonTerminal(name));
+        } else {
+            terminal.push(c);
+        }
+    }
+    if !terminal.is_empty() || ret.is_empty() {
+        ret.push(Symbol::Terminal(terminal));
+    }
+    ret
+}
+
+fn collect_case_insensitive_letters(node: &Node, ret: &mut Vec<char>) {
+    match node {
+        Node::Alternatives(nodes) | Node::Concatenation(nodes) => {
+            nodes
+                .iter()
+                .for_each(|n| collect_case_insensitive_letters(n, ret));
+        }
+        Node::Repetition { node, .. } | Node::Group(node) | Node::Optional(node) => {
+            collect_case_insensitive_letters(node, ret);
+        }
+        Node::String(s) if !s.is_case_sensitive() => {
+            for c in s.as_str().chars().filter(|c| c.is_ascii_alphabetic()) {
+                let c = c.to_ascii_lowercase();
+                if !ret.contains(&c) {
+                    ret.push(c);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Creates the rules used by the expanded case-insensitive strings.
+fn extract_rules_for_case_insensitive_letters(names: &NestedRuleNames) -> Vec<GrammarRule> {
+    names
+        .letters()
+        .map(|(c, name)| GrammarRule {
+            name: name.to_owned(),
+            alternatives: vec![
+                vec![Symbol::Terminal(c.to_string())],
+                vec![Symbol::Terminal(c.to_ascii_uppercase().to_string())],
+            ],
+            origin: RuleOrigin::CaseInsensitiveLetter,
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn nested_rule_origin(node: &Node) -> RuleOrigin {
+    match node {
+        Node::Repetition { repeat, .. } => {
+            let (min, max) = match repeat {
+                Repeat::Specific(n) => (*n, Some(*n)),
+                Repeat::Variable { min, max } => (min.unwrap_or(0), *max),
+            };
+            RuleOrigin::Repetition { min, max }
+        }
+        Node::TerminalValues(TerminalValues::Range(start, end)) => RuleOrigin::Range(*start, *end),
+        _ => RuleOrigin::Group,
+    }
+}
+
+fn extract_rules_for_nested_groups(
+    names: &NestedRuleNames,
+    owners: &[(Node, &str)],
+    case_mode: CaseMode,
+    separated: &HashSet<&str>,
+    separator: &Symbol,
+) -> anyhow::Result<Vec<GrammarRule>> {
+    let mut ret = Vec::new();
+    for (node, name) in names.iter() {
+        let mut node_owners = owners.iter().filter(|(n, _)| n == node).map(|(_, o)| *o);
+        // a node shared with a glued rule stays glued
+        let separator = node_owners
+            .clone()
+            .all(|owner| separated.contains(owner))
+            .then_some(separator);
+        let alternatives = json_rule_body_from_group(node, names, true, case_mode, separator)
+            .with_context(|| {
+                // the nested rules are named after the rule they were extracted from
+                format!("couldn't convert rule `{}`", node_owners.next().unwrap())
+            })?;
+        ret.push(GrammarRule {
+            name: name.to_owned(),
+            alternatives,
+            origin: nested_rule_origin(node),
+            ..Default::default()
+        });
+    }
+    Ok(ret)
+}
+
+/// Loads the grammar from the ABNF rules; the nested groups, options and repetitions become rules
+/// of their own.
+pub fn ruleset_to_grammar(
+    rules: &[Rule],
+    start: &str,
+    options: &ConversionOptions,
+) -> anyhow::Result<Grammar> {
+    let ConversionOptions {
+        case_mode,
+        name_style,
+        whitespace,
+        weights,
+    } = options;
+    let case_mode = *case_mode;
+    let mut grammar = Grammar {
+        start: start.to_owned(),
+        ..Default::default()
+    };
+
+    // the start symbol expected by the grammar-based fuzzers
+    if start != START_RULE {
+        grammar.rules.push(GrammarRule {
+            name: START_RULE.to_owned(),
+            alternatives: vec![vec![Symbol::NonTerminal(start.to_owned())]],
+            origin: RuleOrigin::Start,
+            ..Default::default()
+        });
+    }
+
+    let mut letters = Vec::new();
+    if case_mode == CaseMode::Expand {
+        for rule in rules {
+            collect_case_insensitive_letters(rule.node(), &mut letters);
+        }
+    }
+    let reserved = rules.iter().map(|rule| rule.name()).chain([START_RULE]);
+    let owners = extract_nested_nodes(rules);
+    let nodes = owners
+        .iter()
+        .map(|(node, _)| node.clone())
+        .collect::<Vec<_>>();
+    let names = NestedRuleNames::new(&nodes, &letters, reserved, *name_style);
+
+    let separated = whitespace.separated_rules(rules, start)?;
+    let separator = &whitespace.separator;
+
+    grammar.rules.extend(extract_rules_for_nested_groups(
+        &names, &owners, case_mode, &separated, separator,
+    )?);
+    for rule in rules {
+        let separator = separated.contains(rule.name()).then_some(separator);
+        let convert = |node| {
+            json_rule_body_from_group(node, &names, false, case_mode, separator)
+                .with_context(|| format!("couldn't convert rule `{}`", rule.name()))
+        };
+        let mut alternatives = Vec::new();
+        let mut rule_weights = Vec::new();
+        if let Some(node_weights) = weights.get(rule)? {
+            // the nested groups become rules of their own, so an ABNF alternative normally expands
+            // to a single one; otherwise its weight is split between them
+            for (node, weight) in top_level_alternatives(rule.node())
+                .into_iter()
+                .zip(node_weights)
+            {
+                let expanded = convert(node)?;
+                let weight = weight / expanded.len() as f64;
+                rule_weights.extend(iter::repeat_n(weight, expanded.len()));
+                alternatives.extend(expanded);
+            }
+        } else {
+            alternatives = convert(rule.node())?;
+        }
+        grammar.rules.push(GrammarRule {
+            name: rule.name().to_owned(),
+            alternatives,
+            origin: RuleOrigin::Rule,
+            weights: rule_weights,
+        });
+    }
+    grammar
+        .rules
+        .extend(extract_rules_for_case_insensitive_letters(&names));
+    // a start rule defined in the grammar comes after the nested rules extracted from it
+    if let Some(idx) = grammar.rules.iter().position(|r| r.name == START_RULE) {
+        let rule = grammar.rules.remove(idx);
+        grammar.rules.insert(0, rule);
+    }
+
+    Ok(grammar)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use abnf::rulelist;
+
+    use super::*;
+    use crate::options::NameStyle;
+
+    const SIMPLE_RULESET: &str = r#"
+a = "a";
+b = "b";
+c = "c";
+
+grp-a-any-bc = a ( b / c );
+grp-a-all-bc = a ( b c );
+
+nested-any-grp = a ( b / (a / c) );
+nested-all-grp = a ( b (a c) );
+
+star-a = *a;
+one-star-a = 1*a;
+star-two-a = *2a;
+one-star-two-a = 1*2a;
+"#;
+
+    fn nt(name: &str) -> Symbol {
+        Symbol::NonTerminal(name.to_owned())
+    }
+
+    #[test]
+    fn nested_group_extraction() {
+        let rules = rulelist(SIMPLE_RULESET).unwrap();
+        let case_mode = CaseMode::Canonical;
+
+        let extra_nodes = extract_nested_nodes(&rules)
+            .into_iter()
+            .map(|(node, _)| node)
+            .collect::<Vec<_>>();
+        assert_eq!(extra_nodes.len(), 10);
+        let names = NestedRuleNames::new(
+            &extra_nodes,
+            &[],
+            rules.iter().map(|r| r.name()),
+            NameStyle::Descriptive,
+        );
+
+        let mut node_iter = extra_nodes.iter();
+        let node0 = node_iter.next().unwrap();
+        assert_eq!("b-or-c", json_rule_name_from_group(node0, true));
+        assert_eq!(
+            vec![vec![nt("b")], vec![nt("c")]],
+            json_rule_body_from_group(node0, &names, true, case_mode, None).unwrap()
+        );
+
+        let node1 = node_iter.next().unwrap();
+        assert_eq!("b-and-c", json_rule_name_from_group(node1, true));
+        assert_eq!(
+            vec![vec![nt("b"), nt("c")]],
+            json_rule_body_from_group(node1, &names, true, case_mode, None).unwrap()
+        );
+
+        let node2 = node_iter.next().unwrap();
+        assert_eq!("b-or-（a-or-c）", json_rule_name_from_group(node2, true));
+        assert_eq!(
+            vec![vec![nt("b")], vec![nt("a-or-c")]],
+            json_rule_body_from_group(node2, &names, true, case_mode, None).unwrap()
+        );
+
+        let node3 = node_iter.next().unwrap();
+        assert_eq!("a-or-c", json_rule_name_from_group(node3, true));
+        assert_eq!(
+            vec![vec![nt("a")], vec![nt("c")]],
+            json_rule_body_from_group(node3, &names, true, case_mode, None).unwrap()
+        );
+
+        let node4 = node_iter.next().unwrap();
+        assert_eq!("b-and-（a-and-c）", json_rule_name_from_group(node4, true));
+        assert_eq!(
+            vec![vec![nt("b"), nt("a-and-c")]],
+            json_rule_body_from_group(node4, &names, true, case_mode, None).unwrap()
+        );
+
+        let node5 = node_iter.next().unwrap();
+        assert_eq!("a-and-c", json_rule_name_from_group(node5, true));
+        assert_eq!(
+            vec![vec![nt("a"), nt("c")]],
+            json_rule_body_from_group(node5, &names, true, case_mode, None).unwrap()
+        );
+    }
+
+    #[test]
+    fn repetitions() {
+        let rules = rulelist(SIMPLE_RULESET).unwrap();
+        let case_mode = CaseMode::Canonical;
+
+        let rep = rules.iter().find(|&r| r.name() == "star-a").unwrap().node();
+        assert_eq!(repetition_rule_name(rep, true), "zero-or-more-as");
+
+        let rep = rules
+            .iter()
+            .find(|&r| r.name() == "one-star-a")
+            .unwrap()
+            .node();
+        assert_eq!(repetition_rule_name(rep, true), "at-least-1-a");
+
+        let rep = rules
+            .iter()
+            .find(|&r| r.name() == "star-two-a")
+            .unwrap()
+            .node();
+        assert_eq!(repetition_rule_name(rep, true), "at-most-2-as");
+
+        let rep = rules
+            .iter()
+            .find(|&r| r.name() == "one-star-two-a")
+            .unwrap()
+            .node();
+        assert_eq!(repetition_rule_name(rep, true), "between-1-and-2-as");
+        assert_eq!(
+            vec![vec![nt("a")], vec![nt("a"), nt("a")]],
+            json_rule_body_from_group(rep, &NestedRuleNames::default(), true, case_mode, None)
+                .unwrap()
+        );
+
+        let rules = rulelist("zero-to-two-a = 0*2a\n").unwrap();
+        let rep = rules[0].node();
+        assert_eq!(repetition_rule_name(rep, true), "between-0-and-2-as");
+        assert_eq!(
+            vec![vec![], vec![nt("a")], vec![nt("a"), nt("a")]],
+            json_rule_body_from_group(rep, &NestedRuleNames::default(), true, case_mode, None)
+                .unwrap()
+        );
+
+        let grammar = ruleset_to_grammar(
+            &rules,
+            "zero-to-two-a",
+            &ConversionOptions {
+                case_mode,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let origins = grammar.rules.iter().map(|r| r.origin).collect::<Vec<_>>();
+        assert_eq!(
+            origins,
+            [
+                RuleOrigin::Start,
+                RuleOrigin::Repetition {
+                    min: 0,
+                    max: Some(2)
+                },
+                RuleOrigin::Rule
+            ]
+        );
+
+        // a start rule defined in the grammar comes before the rules extracted from it
+        let rules = rulelist("start = \"(\" x \")\"\nx = 2*3y\ny = \"a\"\n").unwrap();
+        let grammar = ruleset_to_grammar(
+            &rules,
+            START_RULE,
+            &ConversionOptions {
+                case_mode,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(grammar.rules[0].name, START_RULE);
+        assert_eq!(grammar.rules[0].origin, RuleOrigin::Rule);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let ruleset = format!(
+            "{SIMPLE_RULESET}\nquote = %x22\nbackslash = \"\\\"\ncontrol = %x00-02 / %x0A.0D\n"
+        );
+        let rules = rulelist(&ruleset).unwrap();
+        let grammar = ruleset_to_grammar(&rules, "a", &ConversionOptions::default())
+            .unwrap()
+            .sample_ranges(256);
+        let json = grammar.to_json().unwrap();
+
+        let parsed: BTreeMap<String, Vec<Vec<String>>> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.len(), grammar.rules.len());
+        for rule in &grammar.rules {
+            let alternatives = rule
+                .alternatives
+                .iter()
+                .map(|alt| {
+                    alt.iter()
+                        .map(|s| s.to_json_string().unwrap())
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(parsed[&format!("<{}>", rule.name)], alternatives);
+        }
+
+        assert_eq!(parsed["<start>"], vec![vec!["<a>"]]);
+        assert_eq!(parsed["<quote>"], vec![vec!["\""]]);
+        assert_eq!(parsed["<backslash>"], vec![vec!["\\"]]);
+        assert_eq!(parsed["<control>"], vec![vec!["<b0-to-b2>"], vec!["\n\r"]]);
+        assert_eq!(
+            parsed["<b0-to-b2>"],
+            vec![vec!["\u{0}"], vec!["\u{1}"], vec!["\u{2}"]]
+        );
+    }
+
+    #[test]
+    fn case_insensitive_strings() {
+        let rules = rulelist("kw = \"add.w\" / %s\"Add\" / \"42\"\n").unwrap();
+        let t = |s: &str| Symbol::Terminal(s.to_owned());
+
+        let grammar = ruleset_to_grammar(&rules, "kw", &ConversionOptions::default()).unwrap();
+        assert_eq!(
+            grammar.rules[1].alternatives,
+            [vec![t("add.w")], vec![t("Add")], vec![t("42")]]
+        );
+
+        let grammar = ruleset_to_grammar(
+            &rules,
+            "kw",
+            &ConversionOptions {
+                case_mode: CaseMode::Expand,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            grammar.rules[1].alternatives,
+            [
+                vec![
+                    nt("a-or-A"),
+                    nt("d-or-D"),
+                    nt("d-or-D"),
+                    t("."),
+                    nt("w-or-W")
+                ],
+                vec![t("Add")],
+                vec![t("42")]
+            ]
+        );
+        let case_rules = grammar.rules[2..]
+            .iter()
+            .map(|r| r.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(case_rules, ["a-or-A", "d-or-D", "w-or-W"]);
+        assert_eq!(grammar.rules[2].alternatives, [vec![t("a")], vec![t("A")]]);
+
+        // the letter rules don't replace the rules of the grammar
+        let rules = rulelist("kw = \"a\" a-or-A\na-or-A = %s\"x\"\n").unwrap();
+        let grammar = ruleset_to_grammar(
+            &rules,
+            "kw",
+            &ConversionOptions {
+                case_mode: CaseMode::Expand,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let names = grammar
+            .rules
+            .iter()
+            .map(|r| r.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, [START_RULE, "kw", "a-or-A", "a-or-A-2"]);
+        assert_eq!(
+            grammar.rules[1].alternatives,
+            [vec![nt("a-or-A-2"), nt("a-or-A")]]
+        );
+    }
+
+    #[test]
+    fn conversion_errors() {
+        let rules = rulelist("a = b / ( \"x\" / %xD800.41 )\nb = <prose>\n").unwrap();
+        let error = ruleset_to_grammar(&rules, "a", &ConversionOptions::default()).unwrap_err();
+        assert_eq!(error.to_string(), "couldn't convert rule `a`");
+        assert_eq!(
+            error.root_cause().to_string(),
+            "`%xD800` is not a valid character"
+        );
+
+        let error =
+            ruleset_to_grammar(&rules[1..], "b", &ConversionOptions::default()).unwrap_err();
+        assert_eq!(error.to_string(), "couldn't convert rule `b`");
+    }
+}
@@ -1,495 +1,355 @@
-use std::{collections::HashSet, fs};
-
-use abnf::{
-    rulelist,
-    types::{Node, Repeat, Rule, TerminalValues},
+mod cli;
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    str,
 };
 
-const NESTED_RULE_START: char = '（';
-const NESTED_RULE_END: char = '）';
-
-fn add_missing_body_brackets(body: String) -> String {
-    if !body.starts_with('[') {
-        format!("[{body}]")
-    } else {
-        body
+use abnf::types::Rule;
+use abnf_converter::{
+    analysis, core_rules, coverage,
+    diff::GrammarDiff,
+    earley::{EarleyParser, NautilusIds},
+    generator,
+    grammar::Grammar,
+    grammar_mutator, nautilus,
+    options::ConversionOptions,
+    preprocess, ruleset_to_grammar,
+    serialize::{
+        AntlrSerializer, DictionarySerializer, GrammarMutatorSerializer, GrammarSerializer,
+        JsonSerializer, NautilusSerializer,
+    },
+    simplify, subset, syntax, validation,
+    validation::Severity,
+    weights::{AlternativeWeights, RuleWeights},
+    START_RULE,
+};
+use anyhow::{bail, Context};
+use cli::OutputFormat;
+
+/// ANTLR expects the grammar name to match the file name.
+fn antlr_grammar_name(path: Option<&PathBuf>) -> String {
+    let stem = path
+        .and_then(|p| p.file_stem())
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "Grammar".to_owned());
+
+    let mut name = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        name.insert(0, 'G');
     }
+    name
 }
 
-// TODO: rename to reflect that it also includes repetitions
-fn extract_nested_grups_from_rules(rules: &[Rule]) -> Vec<Node> {
-    let mut ret = Vec::new();
-    for node in rules.iter().map(|r| r.node()) {
-        extract_nested_groups_from_node(node, &mut ret);
+/// Reads the grammar from the given file or stdin, along with the weights annotated in it.
+fn load_rules(input: Option<&PathBuf>) -> anyhow::Result<(Vec<Rule>, RuleWeights)> {
+    let mut abnf_str = if let Some(path) = input {
+        fs::read_to_string(path).with_context(|| format!("couldn't read {}", path.display()))?
+    } else {
+        let mut s = String::new();
+        io::stdin().read_to_string(&mut s)?;
+        s
+    };
+    // the ABNF parser requires a trailing newline
+    if !abnf_str.ends_with('\n') {
+        abnf_str.push('\n');
     }
-    ret
+    let file = match input {
+        Some(path) => path.display().to_string(),
+        None => "<stdin>".to_owned(),
+    };
+    let abnf_rules = syntax::parse_rulelist(&abnf_str, &file)?;
+    let weights = RuleWeights::from_annotations(&abnf_str)
+        .with_context(|| format!("couldn't parse the weights in {file}"))?;
+    Ok((preprocess::merge_incremental_rules(abnf_rules)?, weights))
 }
 
-fn extract_nested_groups_from_node(node: &Node, ret: &mut Vec<Node>) {
-    match node {
-        Node::Alternatives(nodes) | Node::Concatenation(nodes) => {
-            nodes
-                .iter()
-                .for_each(|n| extract_nested_groups_from_node(n, ret));
-        }
-        Node::Repetition { repeat: _, node: n } => {
-            ret.push(node.clone());
-            extract_nested_groups_from_node(n, ret);
-        }
-        Node::Group(n) => {
-            ret.push(node.clone());
-            extract_nested_groups_from_node(n, ret);
-        }
-        Node::Optional(n) => {
-            ret.push(node.clone());
-            extract_nested_groups_from_node(n, ret);
-        }
-        Node::TerminalValues(tv) if matches!(tv, TerminalValues::Range(..)) => {
-            ret.push(node.clone());
-        }
-        _ => {}
+/// Writes every seed into a separate file in the given directory.
+fn write_seeds(dir: &Path, seeds: &[String]) -> anyhow::Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("couldn't create {}", dir.display()))?;
+    for (i, seed) in seeds.iter().enumerate() {
+        let path = dir.join(format!("{i:06}"));
+        fs::write(&path, seed).with_context(|| format!("couldn't write {}", path.display()))?;
     }
+    Ok(())
 }
 
-fn repetition_rule_name(node: &Node, toplevel: bool) -> String {
-    let Node::Repetition { repeat, node } = node else {
-        unreachable!();
-    };
-
-    let mut plural = true;
-    let prefix = match repeat {
-        Repeat::Specific(n) => {
-            format!("{n}")
-        }
-        Repeat::Variable { min, max } => {
-            if let (Some(min), Some(max)) = (min, max) {
-                format!("between-{min}-and-{max}")
-            } else if let Some(min) = min {
-                if *min == 1 {
-                    plural = false;
-                }
-                format!("at-least-{min}")
-            } else if let Some(max) = max {
-                if *max == 1 {
-                    plural = false;
-                }
-                format!("at-most-{max}")
-            } else {
-                format!("zero-or-more")
-            }
-        }
-    };
-    let rule_name = json_rule_name_from_group(&*node, toplevel);
-    if matches!(&**node, Node::Group(..) | Node::Repetition { .. }) {
-        plural = false;
+/// Parses every file in the directory; the output directory gets the Nautilus grammar in
+/// `nautilus.json`, sampled keeping the characters of the files, the derivation trees in
+/// `corpus/<name>` as LibAFL Nautilus inputs of that grammar, and the points where parsing failed
+/// in `failures/<name>.json`; the files that aren't valid UTF-8 fail like the ones that don't
+/// parse.
+fn parse_files(
+    grammar: &Grammar,
+    max_range_size: usize,
+    dir: &Path,
+    out: &Path,
+) -> anyhow::Result<(usize, usize)> {
+    let mut paths = fs::read_dir(dir)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<io::Result<Vec<_>>>()
+        })
+        .with_context(|| format!("couldn't read {}", dir.display()))?;
+    paths.retain(|path| path.is_file());
+    paths.sort();
+    let inputs = paths
+        .iter()
+        .map(|path| fs::read(path).with_context(|| format!("couldn't read {}", path.display())))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    // the trees refer to the sampled grammar, so it needs to keep the characters they contain
+    let kept = inputs
+        .iter()
+        .filter_map(|input| str::from_utf8(input).ok())
+        .flat_map(str::chars)
+        .collect::<BTreeSet<_>>();
+    let parser = EarleyParser::new(grammar);
+    let ids = NautilusIds::new(grammar, max_range_size, &kept);
+    let (corpus, failures) = (out.join("corpus"), out.join("failures"));
+    for dir in [&corpus, &failures] {
+        fs::create_dir_all(dir).with_context(|| format!("couldn't create {}", dir.display()))?;
     }
-
-    format!("{prefix}-{rule_name}{}", if plural { "s" } else { "" })
-}
-
-fn json_rule_name_from_group(node: &Node, toplevel: bool) -> String {
-    let mut ret = String::new();
-    match node {
-        Node::Alternatives(nodes) => {
-            if !toplevel {
-                ret.push(NESTED_RULE_START);
-            }
-            let mut node_iter = nodes.iter().peekable();
-            while let Some(node) = node_iter.next() {
-                let name = json_rule_name_from_group(node, false);
-                ret.push_str(&name);
-                if node_iter.peek().is_some() {
-                    ret.push_str("-or-");
-                }
-            }
-            if !toplevel {
-                ret.push(NESTED_RULE_END);
-            }
-        }
-        Node::Concatenation(nodes) => {
-            if !toplevel {
-                ret.push(NESTED_RULE_START);
-            }
-            let mut node_iter = nodes.iter().peekable();
-            while let Some(node) = node_iter.next() {
-                let name = json_rule_name_from_group(node, false);
-                ret.push_str(&name);
-                if node_iter.peek().is_some() {
-                    ret.push_str("-and-");
-                }
-            }
-            if !toplevel {
-                ret.push(NESTED_RULE_END);
+    let path = out.join("nautilus.json");
+    fs::write(&path, nautilus::grammar_to_nautilus(ids.sampled()))
+        .with_context(|| format!("couldn't write {}", path.display()))?;
+
+    let mut parsed = 0;
+    for (path, input) in paths.iter().zip(&inputs) {
+        let name = path.file_name().unwrap().to_string_lossy();
+        let (out_path, contents) = match parser.parse_bytes(input) {
+            Ok(tree) => {
+                parsed += 1;
+                let tree = ids
+                    .tree(&tree)
+                    .expect("the sampled grammar keeps the characters of the inputs");
+                (corpus.join(&*name), tree.to_libafl_input())
             }
-        }
-        node @ Node::Repetition { .. } => {
-            if !toplevel {
-                ret.push(NESTED_RULE_START);
-            }
-            ret.push_str(&repetition_rule_name(node, false));
-            if !toplevel {
-                ret.push(NESTED_RULE_END);
-            }
-        }
-        Node::Rulename(rule) => {
-            ret.push_str(&rule);
-        }
-        Node::Group(node) => {
-            ret.push_str(&json_rule_name_from_group(&*node, toplevel));
-        }
-        Node::Optional(node) => {
-            ret.push_str(&format!(
-                "optional-{}",
-                json_rule_name_from_group(node, false)
-            ));
-        }
-        Node::String(s) => {
-            ret.push_str(s.as_str());
-        }
-        Node::TerminalValues(tv) => match tv {
-            TerminalValues::Range(start, end) => {
-                if !toplevel {
-                    ret.push(NESTED_RULE_START);
-                }
-                ret.push_str(&format!("b{start}-to-b{end}"));
-                if !toplevel {
-                    ret.push(NESTED_RULE_END);
-                }
-            }
-            TerminalValues::Concatenation(cs) => {
-                if !toplevel {
-                    ret.push(NESTED_RULE_START);
-                }
-                let mut val_iter = cs.iter().copied().peekable();
-                while let Some(val) = val_iter.next() {
-                    let c = char::from_u32(val).unwrap();
-                    let s = format!("{:?}", c.to_string());
-                    ret.push_str(&s);
-
-                    if val_iter.peek().is_some() {
-                        ret.push_str("-and-");
-                    }
-                }
-                if !toplevel {
-                    ret.push(NESTED_RULE_END);
-                }
+            Err(failure) => {
+                eprintln!("{}:{failure}", path.display());
+                let path = failures.join(format!("{name}.json"));
+                (path, failure.to_json().into_bytes())
             }
-        },
-        _ => unimplemented!(),
+        };
+        fs::write(&out_path, contents)
+            .with_context(|| format!("couldn't write {}", out_path.display()))?;
     }
 
-    ret.retain(|c| c != '.');
-    let ret = ret.replace("_", "underscore");
-    let ret = ret.replace("--", "-minus");
-
-    ret
+    Ok((parsed, paths.len()))
 }
 
-fn json_rule_body_from_group(
-    main_node: &Node,
-    rules: &[Rule],
-    extra_nodes: &[Node],
-    toplevel: bool,
-) -> String {
-    let mut ret = String::new();
-
-    if !toplevel && extra_nodes.contains(main_node) {
-        ret.push_str(&format!(
-            "\"<{}>\"",
-            json_rule_name_from_group(main_node, true)
-        ));
-        return ret;
+fn main() -> anyhow::Result<()> {
+    let args = cli::Args::parse(std::env::args().skip(1))?;
+    if args.help {
+        print!("{}", cli::USAGE);
+        return Ok(());
     }
 
-    match main_node {
-        Node::Alternatives(nodes) => {
-            let mut node_iter = nodes.iter().peekable();
-            while let Some(node) = node_iter.next() {
-                let name = format!(
-                    "[{}]",
-                    json_rule_body_from_group(node, rules, extra_nodes, false)
-                );
-                ret.push_str(&name);
-                if node_iter.peek().is_some() {
-                    ret.push_str(", ");
-                }
-            }
-        }
-        Node::Concatenation(nodes) => {
-            let mut node_iter = nodes.iter().peekable();
-            while let Some(node) = node_iter.next() {
-                let name = format!(
-                    "{}",
-                    json_rule_body_from_group(node, rules, extra_nodes, false)
-                );
-                ret.push_str(&name);
-                if node_iter.peek().is_some() {
-                    ret.push_str(", ");
-                }
-            }
-        }
-        Node::Repetition { repeat, node } => match repeat {
-            Repeat::Specific(n) => {
-                let single = json_rule_body_from_group(&*node, rules, extra_nodes, false);
-                ret.push('[');
-                for i in 0..*n {
-                    ret.push_str(&format!("{single}"));
-                    if i < n - 1 {
-                        ret.push_str(", ");
-                    }
-                }
-                ret.push(']');
-            }
-            Repeat::Variable { min, max } => {
-                if let (Some(_min), Some(_max)) = (min, max) {
-                    unimplemented!();
-                } else if let Some(min) = min {
-                    let single = json_rule_body_from_group(&*node, rules, extra_nodes, false);
-                    ret.push('[');
-                    for i in 0..*min {
-                        ret.push_str(&format!("{single}"));
-                        if i < *min - 1 {
-                            ret.push_str(", ");
-                        }
-                    }
-                    let more = json_rule_name_from_group(main_node, false);
-                    let rest = format!("], [{single}, \"<{more}>\"]");
-                    ret.push_str(&rest);
-                } else if let Some(max) = max {
-                    ret.push_str("[], ");
-                    let single = json_rule_body_from_group(&*node, rules, extra_nodes, false);
-                    for i in 0..*max {
-                        ret.push('[');
-                        for j in 0..(i + 1) {
-                            ret.push_str(&format!("{single}"));
-                            if j < i {
-                                ret.push_str(", ");
-                            }
-                        }
-                        ret.push(']');
-                        if i < *max - 1 {
-                            ret.push_str(", ");
-                        }
-                    }
-                } else {
-                    let single = json_rule_body_from_group(&*node, rules, extra_nodes, false);
-                    let more = json_rule_body_from_group(main_node, rules, extra_nodes, false);
-                    let rest = format!("[], [{single}, {more}]");
-                    ret.push_str(&rest);
-                }
-            }
-        },
-        Node::Rulename(rule) => {
-            ret.push_str(&format!("\"<{rule}>\""));
-        }
-        Node::Group(node) => {
-            ret.push_str(&format!(
-                "{}",
-                json_rule_body_from_group(&*node, rules, extra_nodes, false)
-            ));
+    let (abnf_rules, mut rule_weights) = load_rules(args.input.as_ref())?;
+    if let Some(old_path) = &args.diff {
+        let (old_rules, _) = load_rules(Some(old_path))?;
+        let diff = GrammarDiff::new(&old_rules, &abnf_rules);
+        if let Some(path) = &args.diff_json {
+            fs::write(path, diff.to_json())
+                .with_context(|| format!("couldn't write {}", path.display()))?;
         }
-        Node::Optional(node) => {
-            ret.push_str(&format!(
-                "[], [{}]",
-                json_rule_body_from_group(node, rules, extra_nodes, false)
-            ));
+        if let Some(path) = &args.output {
+            fs::write(path, diff.to_string())
+                .with_context(|| format!("couldn't write {}", path.display()))?;
+        } else {
+            print!("{diff}");
         }
-        Node::String(s) => {
-            let s = if s.as_str() == "\\" {
-                format!("\"\\{}\"", s.as_str())
-            } else {
-                format!("\"{}\"", s.as_str())
-            };
-            ret.push_str(&s);
-        }
-        Node::TerminalValues(tv) => match tv {
-            TerminalValues::Range(start, end) => {
-                let mut val_iter = (*start..=*end).peekable();
-                while let Some(val) = val_iter.next() {
-                    let c = char::from_u32(val).unwrap();
-                    let s = format!("[{:?}]", c.to_string());
-                    ret.push_str(&s);
+        return Ok(());
+    }
 
-                    if val_iter.peek().is_some() {
-                        ret.push_str(", ");
-                    }
-                }
-            }
-            TerminalValues::Concatenation(cs) => {
-                let mut val_iter = cs.iter().copied().peekable();
-                while let Some(val) = val_iter.next() {
-                    let c = char::from_u32(val).unwrap();
-                    let s = format!("[{:?}]", c.to_string());
-                    ret.push_str(&s);
+    let prose_replacements = if let Some(path) = &args.prose {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("couldn't read {}", path.display()))?;
+        serde_json::from_str(&json).with_context(|| {
+            format!(
+                "couldn't parse the prose replacements in {}",
+                path.display()
+            )
+        })?
+    } else {
+        Default::default()
+    };
+    let mut abnf_rules = preprocess::replace_prose(abnf_rules, &prose_replacements)?;
+    if !args.no_core_rules {
+        core_rules::add_missing_core_rules(&mut abnf_rules);
+    }
 
-                    if val_iter.peek().is_some() {
-                        ret.push_str(", ");
-                    }
-                }
-            }
+    // the weights given in the file take precedence over the annotated ones
+    if let Some(path) = &args.weights {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("couldn't read {}", path.display()))?;
+        rule_weights.extend(
+            RuleWeights::from_json(&json)
+                .with_context(|| format!("couldn't parse the weights in {}", path.display()))?,
+        );
+    }
+    let mut weights = AlternativeWeights::new(&abnf_rules, &rule_weights)?;
+
+    let mut subset = subset::Subset::default();
+    if let Some(path) = &args.subset_config {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("couldn't read {}", path.display()))?;
+        subset = subset::Subset::from_json(&json)
+            .with_context(|| format!("couldn't parse the subset config in {}", path.display()))?;
+    }
+    subset.extend(args.subset.clone());
+
+    // ensure that the start rule exists
+    let start = match args.start.clone().or(subset.start.clone()) {
+        Some(start) => start,
+        None => match abnf_rules.first() {
+            Some(rule) => rule.name().to_owned(),
+            None => bail!("the grammar doesn't contain any rules"),
         },
-        _ => unimplemented!(),
+    };
+    if !abnf_rules.iter().any(|rule| rule.name() == start) {
+        bail!("the start rule `{start}` is not defined in the grammar");
     }
-    ret
-}
-
-fn extract_rules_for_nested_groups(rules: &[Rule], extra_nodes: &[Node]) -> String {
-    let mut ret = String::new();
-
-    let start_rule = format!("\"<start>\": [[\"program\"]]");
-    ret.push_str(&format!("  {start_rule},\n"));
-
-    if extra_nodes.is_empty() {
-        return ret;
+    if start != START_RULE && abnf_rules.iter().any(|rule| rule.name() == START_RULE) {
+        bail!("the `{START_RULE}` rule name is reserved for the start symbol");
     }
 
-    let mut known_rule_names = HashSet::new();
-    for node in extra_nodes {
-        let rule_name = json_rule_name_from_group(&node, true);
-        if !known_rule_names.insert(rule_name.clone()) {
-            continue;
-        }
-        let body = json_rule_body_from_group(&node, rules, &extra_nodes, true);
-        let body = add_missing_body_brackets(body);
-        let rule = format!("\"<{rule_name}>\": [{body}]");
-        ret.push_str(&format!("  {rule},\n"));
+    // a start rule that is given keeps only the rules reachable from it, like a subset
+    if args.start.is_some() || !subset.is_empty() {
+        abnf_rules = subset::subset_rules(abnf_rules, &start, &subset, &mut weights)?;
     }
-    ret
-}
-
-fn ruleset_to_json(rules: &[Rule]) -> String {
-    let mut ret = String::new();
-    ret.push_str("{\n");
-
-    let extra_nodes = extract_nested_grups_from_rules(rules);
 
-    ret.push_str(&extract_rules_for_nested_groups(rules, &extra_nodes));
-    let mut rule_iter = rules.iter().peekable();
-    while let Some(rule) = rule_iter.next() {
-        ret.push_str(&rule_to_json(rule, &rules, &extra_nodes));
-
-        if rule_iter.peek().is_some() {
-            ret.push_str(",\n");
-        }
+    // report any issues with the grammar before converting it
+    let issues = validation::validate(&abnf_rules, &start);
+    for issue in &issues {
+        eprintln!("{issue}");
+    }
+    let error_count = issues
+        .iter()
+        .filter(|issue| issue.severity() == Severity::Error)
+        .count();
+    if error_count != 0 && !args.allow_invalid {
+        bail!("the grammar has {error_count} error(s); use `--allow-invalid` to convert it anyway");
     }
-    ret.push_str("\n}");
-    ret
-}
-
-fn rule_to_json(rule: &Rule, rules: &[Rule], extra_nodes: &[Node]) -> String {
-    let name = rule.name();
-    let body = json_rule_body_from_group(rule.node(), rules, extra_nodes, false);
-    let body = add_missing_body_brackets(body);
-    format!("  \"<{name}>\": [{body}]")
-}
-
-fn main() {
-    let abnf_str = fs::read_to_string("/home/ljedrz/downloads/aleo_simplified.abnf").unwrap();
-    let abnf_rules = rulelist(&abnf_str).unwrap();
-    let json = ruleset_to_json(&abnf_rules);
-    println!("{json}");
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    const SIMPLE_RULESET: &str = r#"
-a = "a";
-b = "b";
-c = "c";
-
-grp-a-any-bc = a ( b / c );
-grp-a-all-bc = a ( b c );
-
-nested-any-grp = a ( b / (a / c) );
-nested-all-grp = a ( b (a c) );
-
-star-a = *a;
-one-star-a = 1*a;
-star-two-a = *2a;
-one-star-two-a = 1*2a;
-"#;
-
-    // println!("{json}");
-
-    #[test]
-    fn nested_group_extraction() {
-        let rules = rulelist(SIMPLE_RULESET).unwrap();
-
-        let extra_nodes = extract_nested_grups_from_rules(&rules);
-        assert_eq!(extra_nodes.len(), 10);
-
-        let mut node_iter = extra_nodes.iter();
-        let node0 = node_iter.next().unwrap();
-        assert_eq!("b-or-c", json_rule_name_from_group(node0, true));
-        assert_eq!(
-            "[\"<b>\"], [\"<c>\"]",
-            json_rule_body_from_group(node0, &rules, &extra_nodes, true)
-        );
-
-        let node1 = node_iter.next().unwrap();
-        assert_eq!("b-and-c", json_rule_name_from_group(node1, true));
-        assert_eq!(
-            "\"<b>\", \"<c>\"",
-            json_rule_body_from_group(node1, &rules, &extra_nodes, true)
-        );
 
-        let node2 = node_iter.next().unwrap();
-        assert_eq!("b-or-（a-or-c）", json_rule_name_from_group(node2, true));
-        assert_eq!(
-            "[\"<b>\"], [\"<a-or-c>\"]",
-            json_rule_body_from_group(node2, &rules, &extra_nodes, true)
+    if let Some(passes) = args.simplify {
+        let before = abnf_rules.len();
+        abnf_rules = simplify::simplify(abnf_rules, &start, passes, &mut weights);
+        eprintln!(
+            "simplified the grammar from {before} to {} rules",
+            abnf_rules.len()
         );
+    }
 
-        let node3 = node_iter.next().unwrap();
-        assert_eq!("a-or-c", json_rule_name_from_group(node3, true));
-        assert_eq!(
-            "[\"<a>\"], [\"<c>\"]",
-            json_rule_body_from_group(node3, &rules, &extra_nodes, true)
-        );
+    let grammar = ruleset_to_grammar(
+        &abnf_rules,
+        &start,
+        &ConversionOptions {
+            case_mode: args.case_mode,
+            name_style: args.name_style,
+            whitespace: args.whitespace.clone(),
+            weights,
+        },
+    )?;
+    if let Some(path) = &args.metadata {
+        let analysis = analysis::Analysis::new(&grammar.sample_ranges(args.max_range_size));
+        fs::write(path, analysis.to_json())
+            .with_context(|| format!("couldn't write {}", path.display()))?;
+    }
 
-        let node4 = node_iter.next().unwrap();
-        assert_eq!("b-and-（a-and-c）", json_rule_name_from_group(node4, true));
-        assert_eq!(
-            "\"<b>\", \"<a-and-c>\"",
-            json_rule_body_from_group(node4, &rules, &extra_nodes, true)
+    let config = generator::GeneratorConfig {
+        max_depth: args.max_depth,
+        max_length: args.max_length,
+    };
+    if let Some(count) = args.generate {
+        // the output is guaranteed to be present by the argument parser
+        let dir = args.output.as_ref().unwrap();
+        let seeds = generator::generate_seeds(&grammar, config, count, args.seed);
+        write_seeds(dir, &seeds)?;
+        eprintln!("generated {} seed(s) in {}", seeds.len(), dir.display());
+        return Ok(());
+    }
+    if args.cover {
+        let dir = args.output.as_ref().unwrap();
+        let coverage = coverage::cover(&grammar, config, args.k_paths, args.seed);
+        write_seeds(dir, &coverage.seeds)?;
+        eprintln!(
+            "generated {} seed(s) in {}; {} of {} alternatives covered",
+            coverage.seeds.len(),
+            dir.display(),
+            coverage.total_alternatives - coverage.uncovered_alternatives.len(),
+            coverage.total_alternatives,
         );
+        if let Some(k) = coverage.k {
+            eprintln!(
+                "{} of {} {k}-paths covered",
+                coverage.total_paths - coverage.uncovered_paths.len(),
+                coverage.total_paths,
+            );
+        }
+        if let Some(path) = &args.coverage_report {
+            fs::write(path, coverage.to_json())
+                .with_context(|| format!("couldn't write {}", path.display()))?;
+        }
+        return Ok(());
+    }
 
-        let node5 = node_iter.next().unwrap();
-        assert_eq!("a-and-c", json_rule_name_from_group(node5, true));
-        assert_eq!(
-            "\"<a>\", \"<c>\"",
-            json_rule_body_from_group(node5, &rules, &extra_nodes, true)
+    if let Some(dir) = &args.parse {
+        let out = args.output.as_ref().unwrap();
+        let (parsed, total) = parse_files(&grammar, args.max_range_size, dir, out)?;
+        eprintln!(
+            "parsed {parsed} of {total} file(s); the results are in {}",
+            out.display()
         );
+        return Ok(());
     }
 
-    #[test]
-    fn repetitions() {
-        let rules = rulelist(SIMPLE_RULESET).unwrap();
-
-        let rep = rules.iter().find(|&r| r.name() == "star-a").unwrap().node();
-        assert_eq!(repetition_rule_name(rep, true), "zero-or-more-as");
-
-        let rep = rules
-            .iter()
-            .find(|&r| r.name() == "one-star-a")
-            .unwrap()
-            .node();
-        assert_eq!(repetition_rule_name(rep, true), "at-least-1-a");
-
-        let rep = rules
-            .iter()
-            .find(|&r| r.name() == "star-two-a")
-            .unwrap()
-            .node();
-        assert_eq!(repetition_rule_name(rep, true), "at-most-2-as");
-
-        let rep = rules
-            .iter()
-            .find(|&r| r.name() == "one-star-two-a")
-            .unwrap()
-            .node();
-        assert_eq!(repetition_rule_name(rep, true), "between-1-and-2-as");
+    let serializer: Box<dyn GrammarSerializer> = match args.format {
+        OutputFormat::Json => Box::new(JsonSerializer {
+            max_range_size: args.max_range_size,
+            probabilities: args.probabilities,
+        }),
+        OutputFormat::Nautilus => Box::new(NautilusSerializer {
+            max_range_size: args.max_range_size,
+        }),
+        OutputFormat::Antlr => Box::new(AntlrSerializer {
+            grammar_name: antlr_grammar_name(args.output.as_ref().or(args.input.as_ref())),
+        }),
+        OutputFormat::GrammarMutator => {
+            let sampled = grammar.sample_ranges(args.max_range_size);
+            let (_, violations) = grammar_mutator::grammar_mutator_profile(&sampled);
+            for violation in &violations {
+                eprintln!("{violation}");
+            }
+            let error_count = violations
+                .iter()
+                .filter(|violation| violation.severity() == Severity::Error)
+                .count();
+            if error_count != 0 && !args.allow_invalid {
+                bail!(
+                    "the grammar violates {error_count} Grammar-Mutator constraint(s); use \
+                     `--allow-invalid` to convert it anyway"
+                );
+            }
+            Box::new(GrammarMutatorSerializer {
+                max_range_size: args.max_range_size,
+            })
+        }
+        OutputFormat::Dictionary => Box::new(DictionarySerializer),
+    };
+    let output = serializer.serialize(&grammar)?;
+    if let Some(path) = &args.output {
+        fs::write(path, output).with_context(|| format!("couldn't write {}", path.display()))?;
+    } else {
+        println!("{output}");
     }
+
+    Ok(())
 }
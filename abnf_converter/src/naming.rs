@@ -0,0 +1,228 @@
+use std::collections::HashSet;
+
+use abnf::types::{Node, Repeat, TerminalValues};
+
+use crate::{json_rule_name_from_group, options::NameStyle};
+
+/// The unique names of the rules created for the nested nodes of the grammar, and for the letters
+/// of the expanded case-insensitive strings.
+#[derive(Debug, Clone, Default)]
+pub struct NestedRuleNames {
+    // nodes can't be hashed, hence the list
+    names: Vec<(Node, String)>,
+    letters: Vec<(char, String)>,
+}
+
+/// Appends a number to the name until it isn't taken, and takes it.
+fn unique_name(name: String, taken: &mut HashSet<String>) -> String {
+    let mut unique_name = name.clone();
+    let mut idx = 2;
+    while taken.contains(&unique_name) {
+        unique_name = format!("{name}-{idx}");
+        idx += 1;
+    }
+    taken.insert(unique_name.clone());
+    unique_name
+}
+
+impl NestedRuleNames {
+    /// Names the lowercase `letters` like `a-or-A` and every structurally distinct node, avoiding
+    /// the `reserved` names and disambiguating the ones that would otherwise share a name.
+    pub fn new<'a, I: IntoIterator<Item = &'a str>>(
+        nodes: &[Node],
+        letters: &[char],
+        reserved: I,
+        style: NameStyle,
+    ) -> Self {
+        let mut taken = reserved
+            .into_iter()
+            .map(|s| s.to_owned())
+            .collect::<HashSet<_>>();
+        let letters = letters
+            .iter()
+            .map(|&c| {
+                let name = format!("{c}-or-{}", c.to_ascii_uppercase());
+                (c, unique_name(name, &mut taken))
+            })
+            .collect();
+        let mut names: Vec<(Node, String)> = Vec::new();
+
+        for node in nodes {
+            if names.iter().any(|(n, _)| n == node) {
+                continue;
+            }
+
+            let name = match style {
+                NameStyle::Descriptive => json_rule_name_from_group(node, true),
+                NameStyle::Hashed => hashed_rule_name(node),
+            };
+            names.push((node.clone(), unique_name(name, &mut taken)));
+        }
+
+        Self { names, letters }
+    }
+
+    pub fn get(&self, node: &Node) -> Option<&str> {
+        self.names
+            .iter()
+            .find(|(n, _)| n == node)
+            .map(|(_, name)| name.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Node, &str)> {
+        self.names.iter().map(|(node, name)| (node, name.as_str()))
+    }
+
+    /// The name of the rule that expands to both cases of the ASCII letter.
+    pub fn letter(&self, c: char) -> Option<&str> {
+        let c = c.to_ascii_lowercase();
+        self.letters
+            .iter()
+            .find(|(letter, _)| *letter == c)
+            .map(|(_, name)| name.as_str())
+    }
+
+    pub fn letters(&self) -> impl Iterator<Item = (char, &str)> {
+        self.letters.iter().map(|(c, name)| (*c, name.as_str()))
+    }
+}
+
+/// A short name consisting of the kind of the node and a hash of its ABNF text.
+fn hashed_rule_name(node: &Node) -> String {
+    let kind = match node {
+        Node::Repetition { .. } => "repetition",
+        Node::Group(..) => "group",
+        Node::Optional(..) => "optional",
+        Node::TerminalValues(TerminalValues::Range(..)) => "range",
+        _ => "node",
+    };
+    let mut text = String::new();
+    write_abnf(node, false, &mut text);
+    format!("{kind}-{:08x}", fnv1a(text.as_bytes()))
+}
+
+/// Writes the node as ABNF text, in a canonical form that doesn't depend on the ABNF parser: the
+/// nested alternatives and concatenations are always parenthesized, and the numeric values are
+/// written in uppercase hexadecimal.
+fn write_abnf(node: &Node, nested: bool, ret: &mut String) {
+    let hex = |values: &[u32]| {
+        let values = values.iter().map(|v| format!("{v:X}")).collect::<Vec<_>>();
+        format!("%x{}", values.join("."))
+    };
+
+    match node {
+        Node::Alternatives(nodes) | Node::Concatenation(nodes) => {
+            let delimiter = match node {
+                Node::Alternatives(_) => " / ",
+                _ => " ",
+            };
+            if nested {
+                ret.push('(');
+            }
+            for (idx, node) in nodes.iter().enumerate() {
+                if idx != 0 {
+                    ret.push_str(delimiter);
+                }
+                write_abnf(node, true, ret);
+            }
+            if nested {
+                ret.push(')');
+            }
+        }
+        Node::Repetition { repeat, node } => {
+            match repeat {
+                Repeat::Specific(n) => ret.push_str(&n.to_string()),
+                Repeat::Variable { min, max } => {
+                    ret.extend(min.map(|min| min.to_string()));
+                    ret.push('*');
+                    ret.extend(max.map(|max| max.to_string()));
+                }
+            }
+            write_abnf(node, true, ret);
+        }
+        Node::Rulename(name) => ret.push_str(name),
+        Node::Group(node) => {
+            ret.push('(');
+            write_abnf(node, false, ret);
+            ret.push(')');
+        }
+        Node::Optional(node) => {
+            ret.push('[');
+            write_abnf(node, false, ret);
+            ret.push(']');
+        }
+        // the quotes can't be escaped in ABNF strings
+        Node::String(s) if s.as_str().contains('"') => {
+            ret.push_str(&hex(&s.as_str().chars().map(u32::from).collect::<Vec<_>>()));
+        }
+        Node::String(s) => {
+            if s.is_case_sensitive() {
+                ret.push_str("%s");
+            }
+            ret.push('"');
+            ret.push_str(s.as_str());
+            ret.push('"');
+        }
+        Node::TerminalValues(TerminalValues::Range(start, end)) => {
+            ret.push_str(&format!("%x{start:X}-{end:X}"));
+        }
+        Node::TerminalValues(TerminalValues::Concatenation(values)) => ret.push_str(&hex(values)),
+        Node::Prose(prose) => {
+            ret.push('<');
+            ret.push_str(prose);
+            ret.push('>');
+        }
+    }
+}
+
+/// The 32-bit FNV-1a hash; unlike the std hashers, it is stable across releases.
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c9dc5, |hash, &b| {
+        (hash ^ b as u32).wrapping_mul(0x01000193)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use abnf::rulelist;
+
+    use super::*;
+    use crate::extract_nested_nodes;
+
+    #[test]
+    fn unique_names() {
+        let rules = rulelist("a = (a) (\"x.y\") (\"xy\") [a] (a)\nab = 1*(\"x\")\n").unwrap();
+        let nodes = extract_nested_nodes(&rules)
+            .into_iter()
+            .map(|(node, _)| node)
+            .collect::<Vec<_>>();
+        let reserved = rules.iter().map(|r| r.name());
+
+        let names = NestedRuleNames::new(&nodes, &[], reserved, NameStyle::Descriptive);
+        assert_eq!(
+            names.iter().map(|(_, name)| name).collect::<Vec<_>>(),
+            ["a-2", "xy", "xy-2", "optional-a", "at-least-1-x", "x"]
+        );
+        assert_eq!(names.get(&nodes[0]), Some("a-2"));
+
+        let reserved = ["a-or-A", "x"];
+        let names = NestedRuleNames::new(&nodes, &['a', 'b'], reserved, NameStyle::Descriptive);
+        assert_eq!(names.letter('A'), Some("a-or-A-2"));
+        assert_eq!(names.letter('b'), Some("b-or-B"));
+        assert_eq!(names.get(nodes.last().unwrap()), Some("x-2"));
+
+        let reserved = rules.iter().map(|r| r.name());
+        let names = NestedRuleNames::new(&nodes, &[], reserved, NameStyle::Hashed);
+        let hashed = names.iter().map(|(_, name)| name).collect::<Vec<_>>();
+        assert!(hashed[0].starts_with("group-") && hashed[0].len() == 14);
+        assert!(hashed[4].starts_with("repetition-"));
+        assert_eq!(hashed.iter().collect::<HashSet<_>>().len(), hashed.len());
+
+        // the names only depend on the ABNF text, so they stay the same across releases
+        let rules = rulelist("a = 1*( %s\"x\" / %x30-39 b [ \"y\" ] )\n").unwrap();
+        let mut text = String::new();
+        write_abnf(rules[0].node(), false, &mut text);
+        assert_eq!(text, r#"1*(%s"x" / (%x30-39 b ["y"]))"#);
+        assert_eq!(hashed_rule_name(rules[0].node()), "repetition-95f3ae8d");
+    }
+}
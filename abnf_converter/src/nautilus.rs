@@ -0,0 +1,195 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    grammar::{to_json_lines, Grammar, Symbol},
+    START_RULE,
+};
+
+/// The start symbol that Nautilus begins its derivations with.
+const NAUTILUS_START: &str = "START";
+
+/// Nautilus non-terminals have to match `[A-Z][a-zA-Z_\-0-9]*`; this maps the grammar's rule names
+/// to unique names that satisfy this constraint.
+fn nautilus_rule_names(grammar: &Grammar) -> HashMap<&str, String> {
+    let mut names = HashMap::new();
+    let mut taken = HashSet::new();
+
+    for rule in &grammar.rules {
+        if names.contains_key(rule.name.as_str()) {
+            continue;
+        }
+
+        let name = if rule.name == START_RULE {
+            NAUTILUS_START.to_owned()
+        } else {
+            let mut name = rule
+                .name
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() || c == '-' {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect::<String>();
+            if let Some(first) = name.get_mut(..1) {
+                first.make_ascii_uppercase();
+            }
+            if !name.starts_with(|c: char| c.is_ascii_uppercase()) {
+                name.insert(0, 'N');
+            }
+            name
+        };
+
+        // the mangling isn't injective, so disambiguate any collisions
+        let mut unique_name = name.clone();
+        let mut idx = 2;
+        while taken.contains(&unique_name) {
+            unique_name = format!("{name}_{idx}");
+            idx += 1;
+        }
+        taken.insert(unique_name.clone());
+        names.insert(rule.name.as_str(), unique_name);
+    }
+
+    names
+}
+
+/// Terminals are literal text in which the non-terminal delimiters need to be escaped.
+fn escape_terminal(s: &str, ret: &mut String) {
+    for c in s.chars() {
+        if matches!(c, '{' | '}' | '\\') {
+            ret.push('\\');
+        }
+        ret.push(c);
+    }
+}
+
+/// Converts the grammar to a list of Nautilus rules; every alternative becomes a separate
+/// `["Rule", "body with {Refs}"]` pair. Nautilus has no notion of character ranges, so they
+/// need to be sampled beforehand.
+pub fn grammar_to_nautilus(grammar: &Grammar) -> String {
+    let names = nautilus_rule_names(grammar);
+
+    let mut rules = Vec::new();
+    for rule in &grammar.rules {
+        let name = &names[rule.name.as_str()];
+        for alternative in &rule.alternatives {
+            let mut body = String::new();
+            for symbol in alternative {
+                match symbol {
+                    Symbol::Terminal(s) => escape_terminal(s, &mut body),
+                    Symbol::NonTerminal(nt) => {
+                        // references to undefined rules are kept verbatim
+                        let nt = names.get(nt.as_str()).map(|s| s.as_str()).unwrap_or(nt);
+                        body.push('{');
+                        body.push_str(nt);
+                        body.push('}');
+                    }
+                    Symbol::Range(..) => {
+                        unreachable!("ranges need to be sampled before serialization")
+                    }
+                }
+            }
+            rules.push([name.clone(), body]);
+        }
+    }
+
+    to_json_lines(&rules)
+}
+
+/// A derivation tree of the Nautilus rules, stored like the `Tree` of LibAFL's Nautilus: the rule
+/// ids of the nodes in pre-order, the size of every node's subtree, and the parent of every node,
+/// the root being its own parent.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NautilusTree {
+    pub rules: Vec<usize>,
+    pub sizes: Vec<usize>,
+    pub paren: Vec<usize>,
+}
+
+impl NautilusTree {
+    /// Builds the tree from the rule ids and the parents of its nodes in pre-order.
+    pub fn new(rules: Vec<usize>, paren: Vec<usize>) -> Self {
+        let mut sizes = vec![1; rules.len()];
+        // the parents precede their children
+        for idx in (1..rules.len()).rev() {
+            sizes[paren[idx]] += sizes[idx];
+        }
+        Self {
+            rules,
+            sizes,
+            paren,
+        }
+    }
+
+    /// Serializes the tree as a LibAFL `NautilusInput`, which LibAFL reads from the files of its
+    /// corpus directories with postcard: the `rules` as `RuleIdOrCustom::Rule` variants, then the
+    /// `sizes` and the `paren`, with every integer and length written as a LEB128 varint.
+    pub fn to_libafl_input(&self) -> Vec<u8> {
+        fn varint(mut n: usize, ret: &mut Vec<u8>) {
+            while n >= 0x80 {
+                ret.push(n as u8 | 0x80);
+                n >>= 7;
+            }
+            ret.push(n as u8);
+        }
+
+        let mut ret = Vec::new();
+        varint(self.rules.len(), &mut ret);
+        for rule in &self.rules {
+            // the index of the `Rule` variant
+            varint(0, &mut ret);
+            varint(*rule, &mut ret);
+        }
+        for values in [&self.sizes, &self.paren] {
+            varint(values.len(), &mut ret);
+            for value in values {
+                varint(*value, &mut ret);
+            }
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use abnf::rulelist;
+
+    use super::*;
+    use crate::{options::ConversionOptions, ruleset_to_grammar};
+
+    #[test]
+    fn nautilus_rules() {
+        let rules = rulelist("program = 1*a b\na = \"{a}\"\nb = (a / \"\\\")\n").unwrap();
+        let grammar = ruleset_to_grammar(&rules, "program", &ConversionOptions::default()).unwrap();
+        let nautilus = grammar_to_nautilus(&grammar);
+
+        let parsed: Vec<(String, String)> = serde_json::from_str(&nautilus).unwrap();
+        assert_eq!(
+            parsed,
+            [
+                ("START", "{Program}"),
+                ("At-least-1-a", "{A}"),
+                ("At-least-1-a", "{A}{At-least-1-a}"),
+                ("A-or-_", "{A}"),
+                ("A-or-_", "\\\\"),
+                ("Program", "{At-least-1-a}{B}"),
+                ("A", "\\{a\\}"),
+                ("B", "{A-or-_}"),
+            ]
+            .map(|(a, b)| (a.to_owned(), b.to_owned()))
+        );
+    }
+
+    #[test]
+    fn libafl_inputs() {
+        let tree = NautilusTree::new(vec![0, 300, 2], vec![0, 0, 1]);
+        assert_eq!(tree.sizes, [3, 2, 1]);
+        assert_eq!(
+            tree.to_libafl_input(),
+            [3, 0, 0, 0, 0xAC, 0x02, 0, 2, 3, 3, 2, 1, 3, 0, 0, 1]
+        );
+    }
+}
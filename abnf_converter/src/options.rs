@@ -0,0 +1,112 @@
+use std::str::FromStr;
+
+use anyhow::bail;
+
+use crate::{weights::AlternativeWeights, whitespace::WhitespacePolicy};
+
+/// How the string literals without `%s` are converted.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CaseMode {
+    #[default]
+    Canonical,
+    Expand,
+}
+
+impl FromStr for CaseMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "canonical" => Ok(Self::Canonical),
+            "expand" => Ok(Self::Expand),
+            _ => bail!("unknown case-insensitivity mode `{s}`"),
+        }
+    }
+}
+
+/// How the rules extracted from nested nodes are named.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum NameStyle {
+    #[default]
+    Descriptive,
+    Hashed,
+}
+
+impl FromStr for NameStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "descriptive" => Ok(Self::Descriptive),
+            "hashed" => Ok(Self::Hashed),
+            _ => bail!("unknown naming style `{s}`"),
+        }
+    }
+}
+
+/// The enabled grammar simplification passes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Passes {
+    pub inline: bool,
+    pub flatten: bool,
+    pub dedup: bool,
+    pub unreachable: bool,
+}
+
+impl FromStr for Passes {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut ret = Self::default();
+        for pass in s.split(',') {
+            match pass.trim() {
+                "inline" => ret.inline = true,
+                "flatten" => ret.flatten = true,
+                "dedup" => ret.dedup = true,
+                "unreachable" => ret.unreachable = true,
+                "all" => {
+                    ret = Self {
+                        inline: true,
+                        flatten: true,
+                        dedup: true,
+                        unreachable: true,
+                    }
+                }
+                pass => bail!("unknown simplification pass `{pass}`"),
+            }
+        }
+        Ok(ret)
+    }
+}
+
+/// The rules whose concatenated elements are separated, e.g. with whitespace.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WhitespaceMode {
+    #[default]
+    None,
+    All,
+    /// Only the rules that aren't made of terminals alone, like ANTLR's parser rules.
+    Syntactic,
+}
+
+impl FromStr for WhitespaceMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "all" => Ok(Self::All),
+            "syntactic" => Ok(Self::Syntactic),
+            _ => bail!("unknown whitespace mode `{s}`"),
+        }
+    }
+}
+
+/// How the ABNF rules are converted to a grammar.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ConversionOptions {
+    pub case_mode: CaseMode,
+    pub name_style: NameStyle,
+    pub whitespace: WhitespacePolicy,
+    pub weights: AlternativeWeights,
+}
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+
+use abnf::types::{Kind, Node, Repeat, Rule, StringLiteral};
+use anyhow::bail;
+
+fn into_alternatives(node: Node) -> Vec<Node> {
+    match node {
+        Node::Alternatives(nodes) => nodes,
+        node => vec![node],
+    }
+}
+
+/// Merges the incremental alternatives (`rule =/ ...`) into the rules they extend.
+pub fn merge_incremental_rules(rules: Vec<Rule>) -> anyhow::Result<Vec<Rule>> {
+    let mut ret: Vec<Rule> = Vec::with_capacity(rules.len());
+
+    for rule in rules {
+        if rule.kind() == Kind::Basic {
+            ret.push(rule);
+            continue;
+        }
+
+        let Some(base) = ret
+            .iter_mut()
+            .find(|r| r.kind() == Kind::Basic && r.name() == rule.name())
+        else {
+            bail!(
+                "the incremental alternative `{} =/ ...` has no preceding base rule",
+                rule.name()
+            );
+        };
+
+        let mut alternatives = into_alternatives(base.node().clone());
+        alternatives.extend(into_alternatives(rule.node().clone()));
+        *base = Rule::new(base.name(), Node::Alternatives(alternatives));
+    }
+
+    Ok(ret)
+}
+
+/// Prose values mapped to the terminals that replace them; every terminal becomes a separate
+/// alternative.
+pub type ProseReplacements = HashMap<String, Vec<String>>;
+
+fn replace_prose_in_node(
+    node: &Node,
+    replacements: &ProseReplacements,
+    toplevel: bool,
+    missing: &mut Vec<String>,
+) -> Node {
+    let replace = |n: &Node, missing: &mut Vec<String>| {
+        replace_prose_in_node(n, replacements, false, missing)
+    };
+
+    match node {
+        Node::Alternatives(nodes) => {
+            Node::Alternatives(nodes.iter().map(|n| replace(n, missing)).collect())
+        }
+        Node::Concatenation(nodes) => {
+            Node::Concatenation(nodes.iter().map(|n| replace(n, missing)).collect())
+        }
+        // the element is never used if it can't occur, like the prose value of `0<pchar>`
+        Node::Repetition {
+            repeat: Repeat::Specific(0) | Repeat::Variable { max: Some(0), .. },
+            ..
+        } => Node::String(StringLiteral::case_sensitive(String::new())),
+        Node::Repetition { repeat, node } => Node::Repetition {
+            repeat: repeat.clone(),
+            node: Box::new(replace(node, missing)),
+        },
+        Node::Group(node) => Node::Group(Box::new(replace(node, missing))),
+        Node::Optional(node) => Node::Optional(Box::new(replace(node, missing))),
+        Node::Prose(prose) => {
+            let terminals = match replacements.get(prose) {
+                Some(terminals) if !terminals.is_empty() => terminals,
+                _ => {
+                    missing.push(prose.clone());
+                    return node.clone();
+                }
+            };
+
+            let mut alternatives = terminals
+                .iter()
+                .map(|s| Node::String(StringLiteral::case_sensitive(s.clone())))
+                .collect::<Vec<_>>();
+            if alternatives.len() == 1 {
+                alternatives.pop().unwrap()
+            } else if toplevel {
+                Node::Alternatives(alternatives)
+            } else {
+                Node::Group(Box::new(Node::Alternatives(alternatives)))
+            }
+        }
+        node => node.clone(),
+    }
+}
+
+/// Replaces the prose values (`<...>`) with the user-supplied terminals.
+pub fn replace_prose(
+    rules: Vec<Rule>,
+    replacements: &ProseReplacements,
+) -> anyhow::Result<Vec<Rule>> {
+    let mut ret = Vec::with_capacity(rules.len());
+    let mut errors = Vec::new();
+
+    for rule in rules {
+        let mut missing = Vec::new();
+        let node = replace_prose_in_node(rule.node(), replacements, true, &mut missing);
+        for prose in missing {
+            errors.push(format!(
+                "rule `{}` uses the prose value `<{prose}>`, which has no replacement",
+                rule.name()
+            ));
+        }
+        ret.push(match rule.kind() {
+            Kind::Basic => Rule::new(rule.name(), node),
+            Kind::Incremental => Rule::incremental(rule.name(), node),
+        });
+    }
+
+    if !errors.is_empty() {
+        bail!("{}", errors.join("\n"));
+    }
+
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use abnf::rulelist;
+
+    use super::*;
+
+    #[test]
+    fn incremental_alternatives() {
+        let rules = rulelist("a = \"x\"\nb = \"y\" / \"z\"\na =/ \"v\" / b\n").unwrap();
+        let rules = merge_incremental_rules(rules).unwrap();
+        assert_eq!(
+            rules,
+            rulelist("a = \"x\" / \"v\" / b\nb = \"y\" / \"z\"\n").unwrap()
+        );
+
+        let rules = rulelist("a =/ \"x\"\n").unwrap();
+        assert!(merge_incremental_rules(rules).is_err());
+    }
+
+    #[test]
+    fn prose_replacement() {
+        let rules =
+            rulelist("a = <any char> 1*<digit>\nb = <digit>\nc = 0<unused> \"y\"\n").unwrap();
+        let mut replacements = ProseReplacements::new();
+        replacements.insert("any char".into(), vec!["x".into()]);
+
+        let err = replace_prose(rules.clone(), &replacements).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "rule `a` uses the prose value `<digit>`, which has no replacement\n\
+             rule `b` uses the prose value `<digit>`, which has no replacement"
+        );
+
+        replacements.insert("digit".into(), vec!["0".into(), "1".into()]);
+        let rules = replace_prose(rules, &replacements).unwrap();
+        assert_eq!(
+            rules,
+            rulelist(
+                "a = %s\"x\" 1*(%s\"0\" / %s\"1\")\nb = %s\"0\" / %s\"1\"\nc = %s\"\" \"y\"\n"
+            )
+            .unwrap()
+        );
+    }
+}
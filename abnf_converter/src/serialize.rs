@@ -0,0 +1,73 @@
+use crate::{
+    antlr::grammar_to_antlr, dictionary::grammar_to_dictionary, grammar::Grammar,
+    grammar_mutator::grammar_mutator_profile, nautilus::grammar_to_nautilus,
+};
+
+/// An output format of the converter.
+pub trait GrammarSerializer {
+    fn serialize(&self, grammar: &Grammar) -> anyhow::Result<String>;
+}
+
+/// The Fuzzingbook-style JSON grammar.
+pub struct JsonSerializer {
+    pub max_range_size: usize,
+    /// Whether the alternatives of the weighted rules are annotated with their probabilities.
+    pub probabilities: bool,
+}
+
+impl GrammarSerializer for JsonSerializer {
+    fn serialize(&self, grammar: &Grammar) -> anyhow::Result<String> {
+        let grammar = grammar.sample_ranges(self.max_range_size);
+        if self.probabilities {
+            grammar.to_json_with_probabilities()
+        } else {
+            grammar.to_json()
+        }
+    }
+}
+
+/// The JSON grammar loaded by Nautilus.
+pub struct NautilusSerializer {
+    pub max_range_size: usize,
+}
+
+impl GrammarSerializer for NautilusSerializer {
+    fn serialize(&self, grammar: &Grammar) -> anyhow::Result<String> {
+        Ok(grammar_to_nautilus(
+            &grammar.sample_ranges(self.max_range_size),
+        ))
+    }
+}
+
+/// The JSON grammar loaded by Grammar-Mutator; the constraint violations can be checked
+/// beforehand with [`grammar_mutator_profile`].
+pub struct GrammarMutatorSerializer {
+    pub max_range_size: usize,
+}
+
+impl GrammarSerializer for GrammarMutatorSerializer {
+    fn serialize(&self, grammar: &Grammar) -> anyhow::Result<String> {
+        let (grammar, _) = grammar_mutator_profile(&grammar.sample_ranges(self.max_range_size));
+        grammar.to_json()
+    }
+}
+
+/// An ANTLR v4 grammar; the ranges don't need to be sampled.
+pub struct AntlrSerializer {
+    pub grammar_name: String,
+}
+
+impl GrammarSerializer for AntlrSerializer {
+    fn serialize(&self, grammar: &Grammar) -> anyhow::Result<String> {
+        grammar_to_antlr(grammar, &self.grammar_name)
+    }
+}
+
+/// An AFL++ dictionary of the terminals of the grammar.
+pub struct DictionarySerializer;
+
+impl GrammarSerializer for DictionarySerializer {
+    fn serialize(&self, grammar: &Grammar) -> anyhow::Result<String> {
+        Ok(grammar_to_dictionary(grammar))
+    }
+}
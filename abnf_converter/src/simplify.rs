@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+
+use abnf::types::{Kind, Node, Rule};
+
+use crate::{
+    options::Passes,
+    validation::{collect_rule_references, reachable_rules},
+    weights::{top_level_alternatives, AlternativeWeights},
+};
+
+fn with_node(rule: &Rule, node: Node) -> Rule {
+    match rule.kind() {
+        Kind::Basic => Rule::new(rule.name(), node),
+        Kind::Incremental => Rule::incremental(rule.name(), node),
+    }
+}
+
+fn replace_references(node: &Node, name: &str, replacement: &Node) -> Node {
+    let replace = |n: &Node| replace_references(n, name, replacement);
+
+    match node {
+        Node::Alternatives(nodes) => Node::Alternatives(nodes.iter().map(replace).collect()),
+        Node::Concatenation(nodes) => Node::Concatenation(nodes.iter().map(replace).collect()),
+        Node::Repetition { repeat, node } => Node::Repetition {
+            repeat: repeat.clone(),
+            node: Box::new(replace(node)),
+        },
+        Node::Group(node) => Node::Group(Box::new(replace(node))),
+        Node::Optional(node) => Node::Optional(Box::new(replace(node))),
+        Node::Rulename(rule) if rule == name => Node::Group(Box::new(replacement.clone())),
+        node => node.clone(),
+    }
+}
+
+/// Replaces the references to the rules used exactly once with their definitions; the start rule,
+/// the recursive rules and the weighted rules are kept.
+pub fn inline_single_use_rules(
+    mut rules: Vec<Rule>,
+    start: &str,
+    weights: &mut AlternativeWeights,
+) -> Vec<Rule> {
+    loop {
+        let mut definitions: HashMap<&str, usize> = HashMap::new();
+        let mut uses: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (idx, rule) in rules.iter().enumerate() {
+            *definitions.entry(rule.name()).or_default() += 1;
+            let mut refs = Vec::new();
+            collect_rule_references(rule.node(), &mut refs);
+            for r in refs {
+                uses.entry(r).or_default().push(idx);
+            }
+        }
+
+        let inlined = rules.iter().enumerate().find_map(|(idx, rule)| {
+            let name = rule.name();
+            match uses.get(name).map(|u| u.as_slice()) {
+                Some(&[user])
+                    if name != start
+                        && user != idx
+                        && definitions[name] == 1
+                        && !weights.contains(name) =>
+                {
+                    Some((idx, user))
+                }
+                _ => None,
+            }
+        });
+        let Some((idx, user)) = inlined else {
+            break;
+        };
+
+        let (name, replacement) = (rules[idx].name(), rules[idx].node());
+        weights.rewrite(|node| vec![replace_references(node, name, replacement)]);
+        let node = replace_references(rules[user].node(), name, replacement);
+        rules[user] = with_node(&rules[user], node);
+        rules.remove(idx);
+    }
+
+    rules
+}
+
+fn flatten_node(node: &Node) -> Node {
+    // groups only need to be kept around the alternatives and concatenations they separate
+    let unwrap_group = |node: Node| match node {
+        Node::Group(inner) => *inner,
+        node => node,
+    };
+
+    match node {
+        Node::Alternatives(nodes) => {
+            let mut ret = Vec::with_capacity(nodes.len());
+            for node in nodes.iter().map(|n| unwrap_group(flatten_node(n))) {
+                match node {
+                    Node::Alternatives(nodes) => ret.extend(nodes),
+                    node => ret.push(node),
+                }
+            }
+            if ret.len() == 1 {
+                ret.pop().unwrap()
+            } else {
+                Node::Alternatives(ret)
+            }
+        }
+        Node::Concatenation(nodes) => {
+            let mut ret = Vec::with_capacity(nodes.len());
+            for node in nodes.iter().map(flatten_node) {
+                match node {
+                    Node::Concatenation(nodes) => ret.extend(nodes),
+                    Node::Group(inner) => match *inner {
+                        Node::Concatenation(nodes) => ret.extend(nodes),
+                        inner => ret.push(Node::Group(Box::new(inner))),
+                    },
+                    node => ret.push(node),
+                }
+            }
+            if ret.len() == 1 {
+                ret.pop().unwrap()
+            } else {
+                Node::Concatenation(ret)
+            }
+        }
+        Node::Repetition { repeat, node } => Node::Repetition {
+            repeat: repeat.clone(),
+            node: Box::new(unwrap_group(flatten_node(node))),
+        },
+        Node::Group(node) => match flatten_node(node) {
+            node @ (Node::Alternatives(..) | Node::Concatenation(..)) => {
+                Node::Group(Box::new(node))
+            }
+            node => node,
+        },
+        Node::Optional(node) => Node::Optional(Box::new(unwrap_group(flatten_node(node)))),
+        node => node.clone(),
+    }
+}
+
+/// Flattens one of the top-level alternatives of a rule, which can turn into several ones.
+fn flatten_alternative(node: &Node) -> Vec<Node> {
+    let node = match flatten_node(node) {
+        Node::Group(node) => *node,
+        node => node,
+    };
+    top_level_alternatives(&node).into_iter().cloned().collect()
+}
+
+/// Removes the redundant groups, and merges the nested alternatives and concatenations.
+pub fn flatten_rules(rules: Vec<Rule>) -> Vec<Rule> {
+    rules
+        .iter()
+        .map(|rule| {
+            let node = match flatten_node(rule.node()) {
+                Node::Group(node) => *node,
+                node => node,
+            };
+            with_node(rule, node)
+        })
+        .collect()
+}
+
+fn dedup_node(node: &Node) -> Node {
+    match node {
+        Node::Alternatives(nodes) => {
+            let mut ret: Vec<Node> = Vec::with_capacity(nodes.len());
+            for node in nodes.iter().map(dedup_node) {
+                if !ret.contains(&node) {
+                    ret.push(node);
+                }
+            }
+            if ret.len() == 1 {
+                ret.pop().unwrap()
+            } else {
+                Node::Alternatives(ret)
+            }
+        }
+        Node::Concatenation(nodes) => Node::Concatenation(nodes.iter().map(dedup_node).collect()),
+        Node::Repetition { repeat, node } => Node::Repetition {
+            repeat: repeat.clone(),
+            node: Box::new(dedup_node(node)),
+        },
+        Node::Group(node) => Node::Group(Box::new(dedup_node(node))),
+        Node::Optional(node) => Node::Optional(Box::new(dedup_node(node))),
+        node => node.clone(),
+    }
+}
+
+/// Removes the alternatives identical to an earlier one.
+pub fn dedup_alternatives(rules: Vec<Rule>) -> Vec<Rule> {
+    rules
+        .iter()
+        .map(|rule| with_node(rule, dedup_node(rule.node())))
+        .collect()
+}
+
+/// Removes the rules that can't be reached from the start rule.
+pub fn remove_unreachable_rules(rules: Vec<Rule>, start: &str) -> Vec<Rule> {
+    let reachable = reachable_rules(&rules, start);
+    rules
+        .iter()
+        .filter(|rule| reachable.contains(rule.name()))
+        .cloned()
+        .collect()
+}
+
+/// Applies the selected simplification passes; the weighted alternatives are rewritten along
+/// with the rules.
+pub fn simplify(
+    mut rules: Vec<Rule>,
+    start: &str,
+    passes: Passes,
+    weights: &mut AlternativeWeights,
+) -> Vec<Rule> {
+    if passes.flatten {
+        rules = flatten_rules(rules);
+        weights.rewrite(flatten_alternative);
+    }
+    // deduplicating before inlining can leave fewer references to a rule
+    if passes.dedup {
+        rules = dedup_alternatives(rules);
+        weights.rewrite(|node| vec![dedup_node(node)]);
+        weights.merge_duplicates();
+    }
+    if passes.inline {
+        rules = inline_single_use_rules(rules, start, weights);
+        // the inlined rules are wrapped in groups
+        if passes.flatten {
+            rules = flatten_rules(rules);
+            weights.rewrite(flatten_alternative);
+        }
+    }
+    if passes.unreachable {
+        rules = remove_unreachable_rules(rules, start);
+    }
+    rules
+}
+
+#[cfg(test)]
+mod tests {
+    use abnf::rulelist;
+
+    use super::*;
+    use crate::weights::RuleWeights;
+
+    #[test]
+    fn simplification_passes() {
+        let rules = rulelist(
+            r#"
+program = 1*statement ( ( "a" / ( "b" / "c" ) ) ( "d" ( "e" ) ) )
+statement = keyword [ ( ";" ) ] / keyword [ ";" ]
+keyword = "let" / "const"
+loop = "(" loop ")"
+unused = "x"
+"#,
+        )
+        .unwrap();
+        let all = Passes {
+            inline: true,
+            flatten: true,
+            dedup: true,
+            unreachable: true,
+        };
+
+        let simplified = simplify(
+            rules.clone(),
+            "program",
+            all,
+            &mut AlternativeWeights::default(),
+        );
+        // the parser always keeps the groups, so the expected rules need to be flattened too
+        let expected =
+            "program = 1*((\"let\" / \"const\") [\";\"]) (\"a\" / \"b\" / \"c\") \"d\" \"e\"\n";
+        assert_eq!(simplified, flatten_rules(rulelist(expected).unwrap()));
+
+        // the recursive rules are never inlined
+        let simplified =
+            inline_single_use_rules(rules.clone(), "program", &mut AlternativeWeights::default());
+        assert_eq!(
+            simplified.iter().map(|r| r.name()).collect::<Vec<_>>(),
+            ["program", "keyword", "loop", "unused"]
+        );
+
+        let simplified = simplify(
+            rules,
+            "program",
+            Passes {
+                unreachable: true,
+                ..Default::default()
+            },
+            &mut AlternativeWeights::default(),
+        );
+        assert_eq!(simplified.len(), 3);
+
+        // the weights follow the rewritten alternatives, and the weighted rules aren't inlined
+        let abnf =
+            "a = ( \"x\" / ( \"y\" ) ) / b ; @weights 4 1\nb = \"z\" / \"z\" ; @weights 1 2\n";
+        let rules = rulelist(abnf).unwrap();
+        let rule_weights = RuleWeights::from_annotations(abnf).unwrap();
+        let mut weights = AlternativeWeights::new(&rules, &rule_weights).unwrap();
+        let simplified = simplify(rules, "a", all, &mut weights);
+        assert_eq!(simplified.len(), 2);
+        assert_eq!(
+            weights.get(&simplified[0]).unwrap(),
+            Some(vec![2.0, 2.0, 1.0])
+        );
+        assert_eq!(weights.get(&simplified[1]).unwrap(), Some(vec![3.0]));
+    }
+}
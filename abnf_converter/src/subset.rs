@@ -0,0 +1,285 @@
+use std::collections::HashSet;
+
+use abnf::types::{Node, Repeat, Rule, StringLiteral};
+use anyhow::{anyhow, bail, Context};
+use serde_json::Value;
+
+use crate::{validation::reachable_rules, weights::AlternativeWeights};
+
+/// The rules to remove from the grammar and the ones to replace with fixed terminals.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Subset {
+    pub start: Option<String>,
+    pub exclude: Vec<String>,
+    /// Every terminal becomes a separate alternative of the stubbed rule.
+    pub stubs: Vec<(String, Vec<String>)>,
+}
+
+impl Subset {
+    /// Parses a config like `{"start": "rule", "exclude": ["rule"], "stubs": {"rule": "text"}}`;
+    /// the stubs may also be lists of terminals.
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let value: Value = serde_json::from_str(json)?;
+        let mut ret = Subset::default();
+
+        let Value::Object(map) = value else {
+            bail!("the subset config needs to be a JSON object");
+        };
+        for (key, value) in map {
+            match (key.as_str(), value) {
+                ("start", Value::String(start)) => ret.start = Some(start),
+                ("exclude", Value::Array(rules)) => {
+                    for rule in rules {
+                        let rule = rule
+                            .as_str()
+                            .ok_or_else(|| anyhow!("the excluded rules need to be strings"))?;
+                        ret.exclude.push(rule.to_owned());
+                    }
+                }
+                ("stubs", Value::Object(stubs)) => {
+                    for (rule, stub) in stubs {
+                        let terminals = match stub {
+                            Value::String(s) => vec![s],
+                            Value::Array(values) => values
+                                .into_iter()
+                                .map(|v| v.as_str().map(|s| s.to_owned()))
+                                .collect::<Option<Vec<_>>>()
+                                .ok_or_else(|| anyhow!("the stub of `{rule}` isn't a string"))?,
+                            _ => bail!("the stub of `{rule}` isn't a string"),
+                        };
+                        ret.add_stub(rule, terminals);
+                    }
+                }
+                (key, _) => bail!("unexpected subset config entry `{key}`"),
+            }
+        }
+
+        Ok(ret)
+    }
+
+    /// Parses a `rule=text` stub.
+    pub fn parse_stub(&mut self, stub: &str) -> anyhow::Result<()> {
+        let (rule, text) = stub
+            .split_once('=')
+            .with_context(|| format!("the stub `{stub}` needs to be in the `rule=text` form"))?;
+        self.add_stub(rule.to_owned(), vec![text.to_owned()]);
+        Ok(())
+    }
+
+    fn add_stub(&mut self, rule: String, terminals: Vec<String>) {
+        match self.stubs.iter_mut().find(|(r, _)| *r == rule) {
+            Some((_, existing)) => existing.extend(terminals),
+            None => self.stubs.push((rule, terminals)),
+        }
+    }
+
+    pub fn extend(&mut self, other: Subset) {
+        if other.start.is_some() {
+            self.start = other.start;
+        }
+        self.exclude.extend(other.exclude);
+        for (rule, terminals) in other.stubs {
+            self.add_stub(rule, terminals);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start.is_none() && self.exclude.is_empty() && self.stubs.is_empty()
+    }
+}
+
+fn empty_node() -> Node {
+    Node::String(StringLiteral::case_sensitive(String::new()))
+}
+
+/// Removes the parts of the node that can't be derived without the excluded rules; returns `None`
+/// if the node can't be derived at all.
+fn prune_node(node: &Node, excluded: &HashSet<String>) -> Option<Node> {
+    match node {
+        Node::Alternatives(nodes) => {
+            let mut nodes = nodes
+                .iter()
+                .filter_map(|n| prune_node(n, excluded))
+                .collect::<Vec<_>>();
+            match nodes.len() {
+                0 => None,
+                1 => nodes.pop(),
+                _ => Some(Node::Alternatives(nodes)),
+            }
+        }
+        Node::Concatenation(nodes) => nodes
+            .iter()
+            .map(|n| prune_node(n, excluded))
+            .collect::<Option<Vec<_>>>()
+            .map(Node::Concatenation),
+        Node::Repetition { repeat, node } => {
+            let min = match repeat {
+                Repeat::Specific(n) => *n,
+                Repeat::Variable { min, .. } => min.unwrap_or(0),
+            };
+            match prune_node(node, excluded) {
+                Some(node) => Some(Node::Repetition {
+                    repeat: repeat.clone(),
+                    node: Box::new(node),
+                }),
+                None if min == 0 => Some(empty_node()),
+                None => None,
+            }
+        }
+        Node::Group(node) => prune_node(node, excluded).map(|n| Node::Group(Box::new(n))),
+        Node::Optional(node) => Some(
+            prune_node(node, excluded)
+                .map(|n| Node::Optional(Box::new(n)))
+                .unwrap_or_else(empty_node),
+        ),
+        Node::Rulename(name) if excluded.contains(name) => None,
+        node => Some(node.clone()),
+    }
+}
+
+/// Replaces the stubbed rules, removes the excluded ones along with the alternatives that depend
+/// on them, and keeps only the rules reachable from the start rule. The stubbed rules lose their
+/// weights, and the removed alternatives take their weights with them.
+pub fn subset_rules(
+    rules: Vec<Rule>,
+    start: &str,
+    subset: &Subset,
+    weights: &mut AlternativeWeights,
+) -> anyhow::Result<Vec<Rule>> {
+    let defined = rules.iter().map(|r| r.name()).collect::<HashSet<_>>();
+    let named = subset
+        .exclude
+        .iter()
+        .chain(subset.stubs.iter().map(|(rule, _)| rule));
+    for rule in named {
+        if !defined.contains(rule.as_str()) {
+            bail!("the excluded or stubbed rule `{rule}` is not defined in the grammar");
+        }
+    }
+
+    let mut rules = rules
+        .into_iter()
+        .map(
+            |rule| match subset.stubs.iter().find(|(r, _)| r == rule.name()) {
+                Some((_, terminals)) => {
+                    weights.remove(rule.name());
+                    let mut nodes = terminals
+                        .iter()
+                        .map(|s| Node::String(StringLiteral::case_sensitive(s.clone())))
+                        .collect::<Vec<_>>();
+                    let node = if nodes.len() == 1 {
+                        nodes.pop().unwrap()
+                    } else {
+                        Node::Alternatives(nodes)
+                    };
+                    Rule::new(rule.name(), node)
+                }
+                None => rule,
+            },
+        )
+        .collect::<Vec<_>>();
+
+    // removing alternatives can make further rules underivable
+    let mut excluded = subset.exclude.iter().cloned().collect::<HashSet<_>>();
+    loop {
+        let mut ret = Vec::with_capacity(rules.len());
+        let mut changed = false;
+        for rule in &rules {
+            if excluded.contains(rule.name()) {
+                changed = true;
+                continue;
+            }
+            match prune_node(rule.node(), &excluded) {
+                Some(node) => {
+                    changed |= node != *rule.node();
+                    ret.push(Rule::new(rule.name(), node));
+                }
+                None => {
+                    excluded.insert(rule.name().to_owned());
+                    changed = true;
+                }
+            }
+        }
+        rules = ret;
+        weights.rewrite(|node| prune_node(node, &excluded).into_iter().collect());
+        if !changed {
+            break;
+        }
+    }
+
+    if excluded.contains(start) {
+        bail!("the start rule `{start}` can't be derived without the excluded rules");
+    }
+
+    let reachable = reachable_rules(&rules, start);
+    Ok(rules
+        .iter()
+        .filter(|rule| reachable.contains(rule.name()))
+        .cloned()
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use abnf::rulelist;
+
+    use super::*;
+    use crate::weights::RuleWeights;
+
+    #[test]
+    fn subsetting() {
+        let rules = rulelist(
+            r#"
+program = 1*item
+item = function / struct / import
+function = "fn " name [ finalize ] body
+finalize = "finalize " body
+struct = "struct " name
+import = "import " name
+name = 1*%x61-7A
+body = "{" *item "}"
+"#,
+        )
+        .unwrap();
+
+        let mut subset = Subset::from_json(
+            r#"{"start": "function", "exclude": ["import"], "stubs": {"name": ["a", "b"]}}"#,
+        )
+        .unwrap();
+        subset.exclude.push("struct".into());
+        assert_eq!(subset.start.as_deref(), Some("function"));
+        assert!(!Subset::from_json(r#"{"start": "function"}"#)
+            .unwrap()
+            .is_empty());
+
+        let rule_weights = RuleWeights::from_json(r#"{"item": [3, 1, 1], "name": [1]}"#).unwrap();
+        let mut weights = AlternativeWeights::new(&rules, &rule_weights).unwrap();
+        let subset = subset_rules(rules.clone(), "function", &subset, &mut weights).unwrap();
+        let expected = r#"
+item = function
+function = "fn " name [ finalize ] body
+finalize = "finalize " body
+name = %s"a" / %s"b"
+body = "{" *item "}"
+"#;
+        let expected = rulelist(expected).unwrap();
+        assert_eq!(subset, expected);
+        // the weights of the remaining alternatives are kept
+        assert_eq!(weights.get(&subset[0]).unwrap(), Some(vec![3.0]));
+        assert!(!weights.contains("name"));
+
+        // nothing is left of the start rule
+        let mut subset = Subset::default();
+        subset.parse_stub("name=x").unwrap();
+        subset.exclude.push("body".into());
+        assert!(subset_rules(
+            rules.clone(),
+            "function",
+            &subset,
+            &mut AlternativeWeights::default(),
+        )
+        .is_err());
+        assert!(subset.parse_stub("name").is_err());
+        assert!(Subset::from_json(r#"{"exclude": [1]}"#).is_err());
+    }
+}
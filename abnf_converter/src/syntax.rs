@@ -0,0 +1,134 @@
+use std::{error::Error, fmt};
+
+use abnf::{rule, rulelist, types::Rule};
+
+/// A syntax error in the ABNF grammar, located in its source file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub file: String,
+    /// The 1-based line of the error.
+    pub line: usize,
+    /// The 1-based column of the error, in characters.
+    pub column: usize,
+    /// The line containing the error.
+    pub text: String,
+    pub message: String,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // keep the tabs so that the caret lines up with the text
+        let indent = self
+            .text
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        write!(
+            f,
+            "{}:{}:{}: {}\n{}\n{indent}^",
+            self.file, self.line, self.column, self.message, self.text
+        )
+    }
+}
+
+impl Error for SyntaxError {}
+
+/// Extracts the line, the column and the description of the innermost error from the message of
+/// the ABNF parser, which looks like `0: at line 2:\n<line>\n    ^\nexpected '<', found x\n\n`.
+fn error_location(message: &str) -> Option<(usize, usize, Option<String>)> {
+    let mut lines = message.lines();
+    let header = lines.next()?;
+    let line = header
+        .strip_prefix("0: at line ")?
+        .split([',', ':'])
+        .next()?
+        .parse()
+        .ok()?;
+    // the caret is placed by the byte offset in the line
+    let text = lines.next()?;
+    let offset = lines.next()?.find('^')?;
+    let column = text
+        .char_indices()
+        .take_while(|&(idx, _)| idx < offset)
+        .count()
+        + 1;
+    let description = lines.next().filter(|l| !l.is_empty()).map(|l| l.to_owned());
+    Some((line, column, description))
+}
+
+/// Parses the rules, reporting the syntax errors with their location; the ABNF parser only points
+/// at the start of the first invalid rule, so that rule is parsed again on its own to find the
+/// actual error.
+pub fn parse_rulelist(input: &str, file: &str) -> Result<Vec<Rule>, SyntaxError> {
+    let error = match rulelist(input) {
+        Ok(rules) => return Ok(rules),
+        Err(error) => error.to_string(),
+    };
+    let source_lines = input.lines().collect::<Vec<_>>();
+    let (mut line, mut column, mut description) = error_location(&error).unwrap_or((1, 1, None));
+
+    // the rule continues until the next line starting with a rule name
+    let first = line - 1;
+    let last = source_lines
+        .iter()
+        .skip(first + 1)
+        .position(|l| l.starts_with(|c: char| c.is_ascii_alphabetic()))
+        .map_or(source_lines.len(), |idx| first + 1 + idx);
+    if first < last {
+        let rule_text = source_lines[first..last].join("\n") + "\n";
+        if let Err(error) = rule(&rule_text) {
+            if let Some((rule_line, rule_column, rule_description)) =
+                error_location(&error.to_string())
+            {
+                line = first + rule_line;
+                column = rule_column;
+                description = rule_description;
+            }
+        }
+    }
+
+    let text = source_lines
+        .get(line - 1)
+        .copied()
+        .unwrap_or_default()
+        .to_owned();
+    let message = match description {
+        Some(description) => format!("invalid ABNF syntax; {description}"),
+        None => "invalid ABNF syntax".to_owned(),
+    };
+    Err(SyntaxError {
+        file: file.to_owned(),
+        line,
+        // the column may point just past the end of the line
+        column: column.min(text.chars().count() + 1),
+        text,
+        message,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn syntax_errors() {
+        let grammar =
+            "program = 1*item\n; a comment\nitem = \"a\" / (\n  \"b\" / \"c\"\nother = %x4G\n";
+        let error = parse_rulelist(grammar, "test.abnf").unwrap_err();
+        assert_eq!((error.line, error.column), (3, 12));
+        assert_eq!(
+            error.to_string(),
+            "test.abnf:3:12: invalid ABNF syntax\nitem = \"a\" / (\n           ^"
+        );
+
+        let error = parse_rulelist("a = \"x\"\nb == a\n", "-").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 4));
+        assert_eq!(error.message, "invalid ABNF syntax; expected '<', found =");
+
+        let message = "0: at line 1:\na = \"é\" (\n         ^\n\n";
+        assert_eq!(error_location(message), Some((1, 9, None)));
+
+        assert_eq!(parse_rulelist("a = \"x\"\n", "-").unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,300 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use abnf::types::{Kind, Node, Repeat, Rule, TerminalValues};
+
+use crate::grammar::CharRange;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found in the grammar before its conversion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    Undefined {
+        rule: String,
+        reference: String,
+    },
+    Unreachable {
+        rule: String,
+        start: String,
+    },
+    Duplicate(String),
+    NonTerminating(String),
+    /// A range of code points without any valid character, like the surrogates.
+    EmptyRange {
+        rule: String,
+        start: u32,
+        end: u32,
+    },
+}
+
+impl Issue {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::Unreachable { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.severity())?;
+        match self {
+            Self::Undefined { rule, reference } => {
+                write!(
+                    f,
+                    "rule `{rule}` references the undefined rule `{reference}`"
+                )
+            }
+            Self::Unreachable { rule, start } => {
+                write!(
+                    f,
+                    "rule `{rule}` is unreachable from the start rule `{start}`"
+                )
+            }
+            Self::Duplicate(rule) => write!(f, "rule `{rule}` is defined more than once"),
+            Self::NonTerminating(rule) => write!(f, "rule `{rule}` can never terminate"),
+            Self::EmptyRange { rule, start, end } => write!(
+                f,
+                "rule `{rule}` contains the range %x{start:X}-{end:X} without any valid character"
+            ),
+        }
+    }
+}
+
+pub fn collect_rule_references<'a>(node: &'a Node, ret: &mut Vec<&'a str>) {
+    match node {
+        Node::Alternatives(nodes) | Node::Concatenation(nodes) => {
+            nodes.iter().for_each(|n| collect_rule_references(n, ret));
+        }
+        Node::Repetition { node, .. } | Node::Group(node) | Node::Optional(node) => {
+            collect_rule_references(node, ret);
+        }
+        Node::Rulename(name) => ret.push(name),
+        _ => {}
+    }
+}
+
+/// Replaces the references to the renamed rules with their new names.
+pub fn rename_references(node: &Node, renamed: &HashMap<&str, &str>) -> Node {
+    let rename = |n: &Node| rename_references(n, renamed);
+
+    match node {
+        Node::Alternatives(nodes) => Node::Alternatives(nodes.iter().map(rename).collect()),
+        Node::Concatenation(nodes) => Node::Concatenation(nodes.iter().map(rename).collect()),
+        Node::Repetition { repeat, node } => Node::Repetition {
+            repeat: repeat.clone(),
+            node: Box::new(rename(node)),
+        },
+        Node::Group(node) => Node::Group(Box::new(rename(node))),
+        Node::Optional(node) => Node::Optional(Box::new(rename(node))),
+        Node::Rulename(name) => match renamed.get(name.as_str()) {
+            Some(new) => Node::Rulename((*new).to_owned()),
+            None => node.clone(),
+        },
+        node => node.clone(),
+    }
+}
+
+fn collect_ranges(node: &Node, ret: &mut Vec<(u32, u32)>) {
+    match node {
+        Node::Alternatives(nodes) | Node::Concatenation(nodes) => {
+            nodes.iter().for_each(|n| collect_ranges(n, ret));
+        }
+        Node::Repetition { node, .. } | Node::Group(node) | Node::Optional(node) => {
+            collect_ranges(node, ret);
+        }
+        Node::TerminalValues(TerminalValues::Range(start, end)) => ret.push((*start, *end)),
+        _ => {}
+    }
+}
+
+/// Returns the names of the rules that can be reached from the start rule, including itself.
+pub fn reachable_rules<'a>(rules: &'a [Rule], start: &str) -> HashSet<&'a str> {
+    let mut references: HashMap<&str, Vec<&str>> = HashMap::new();
+    for rule in rules {
+        collect_rule_references(rule.node(), references.entry(rule.name()).or_default());
+    }
+
+    let mut ret = HashSet::new();
+    let mut queue = rules
+        .iter()
+        .map(|rule| rule.name())
+        .filter(|&name| name == start)
+        .take(1)
+        .collect::<Vec<_>>();
+    while let Some(name) = queue.pop() {
+        if ret.insert(name) {
+            if let Some(refs) = references.get(name) {
+                queue.extend(refs.iter().copied());
+            }
+        }
+    }
+
+    ret
+}
+
+fn is_productive(node: &Node, productive: &HashSet<&str>) -> bool {
+    match node {
+        Node::Alternatives(nodes) => nodes.iter().any(|n| is_productive(n, productive)),
+        Node::Concatenation(nodes) => nodes.iter().all(|n| is_productive(n, productive)),
+        Node::Repetition { repeat, node } => {
+            let min = match repeat {
+                Repeat::Specific(n) => *n,
+                Repeat::Variable { min, .. } => min.unwrap_or(0),
+            };
+            min == 0 || is_productive(node, productive)
+        }
+        Node::Group(node) => is_productive(node, productive),
+        Node::Optional(_) => true,
+        Node::Rulename(name) => productive.contains(name.as_str()),
+        Node::String(_) | Node::TerminalValues(_) | Node::Prose(_) => true,
+    }
+}
+
+/// Returns the names of the rules that can derive a finite string of terminals.
+fn productive_rules(rules: &[Rule]) -> HashSet<&str> {
+    let mut ret = HashSet::new();
+    loop {
+        let new = rules
+            .iter()
+            .filter(|rule| !ret.contains(rule.name()) && is_productive(rule.node(), &ret))
+            .map(|rule| rule.name())
+            .collect::<Vec<_>>();
+        if new.is_empty() {
+            break;
+        }
+        ret.extend(new);
+    }
+    ret
+}
+
+/// Checks the grammar for undefined, duplicate, unreachable and non-terminating rules, and for
+/// ranges that can't be sampled.
+pub fn validate(rules: &[Rule], start: &str) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    let defined = rules.iter().map(|rule| rule.name()).collect::<HashSet<_>>();
+    let reachable = reachable_rules(rules, start);
+    let productive = productive_rules(rules);
+
+    let mut seen = HashSet::new();
+    for rule in rules {
+        let name = rule.name();
+
+        if rule.kind() == Kind::Basic && !seen.insert(name) {
+            issues.push(Issue::Duplicate(name.to_owned()));
+            continue;
+        }
+
+        let mut references = Vec::new();
+        collect_rule_references(rule.node(), &mut references);
+        let mut undefined = HashSet::new();
+        for reference in references {
+            if !defined.contains(reference) && undefined.insert(reference) {
+                issues.push(Issue::Undefined {
+                    rule: name.to_owned(),
+                    reference: reference.to_owned(),
+                });
+            }
+        }
+
+        let mut ranges = Vec::new();
+        collect_ranges(rule.node(), &mut ranges);
+        for (start, end) in ranges {
+            if CharRange::new(start, end).is_empty() {
+                issues.push(Issue::EmptyRange {
+                    rule: name.to_owned(),
+                    start,
+                    end,
+                });
+            }
+        }
+
+        if !reachable.contains(name) {
+            issues.push(Issue::Unreachable {
+                rule: name.to_owned(),
+                start: start.to_owned(),
+            });
+        }
+
+        if !productive.contains(name) {
+            issues.push(Issue::NonTerminating(name.to_owned()));
+        }
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use abnf::rulelist;
+
+    use super::*;
+
+    #[test]
+    fn grammar_issues() {
+        let rules = rulelist(
+            r#"
+program = 1*item [ loop ]
+item = "a" / missing
+loop = "(" loop ")"
+unused = "b"
+item = "c"
+surrogate = %xD800-DFFF
+"#,
+        )
+        .unwrap();
+
+        let issues = validate(&rules, "program");
+        assert_eq!(
+            issues,
+            [
+                Issue::Undefined {
+                    rule: "item".into(),
+                    reference: "missing".into()
+                },
+                Issue::NonTerminating("loop".into()),
+                Issue::Unreachable {
+                    rule: "unused".into(),
+                    start: "program".into()
+                },
+                Issue::Duplicate("item".into()),
+                Issue::EmptyRange {
+                    rule: "surrogate".into(),
+                    start: 0xD800,
+                    end: 0xDFFF
+                },
+                Issue::Unreachable {
+                    rule: "surrogate".into(),
+                    start: "program".into()
+                },
+            ]
+        );
+        assert_eq!(
+            issues[0].to_string(),
+            "error: rule `item` references the undefined rule `missing`"
+        );
+        assert_eq!(issues[2].severity(), Severity::Warning);
+        assert_eq!(
+            issues[4].to_string(),
+            "error: rule `surrogate` contains the range %xD800-DFFF without any valid character"
+        );
+    }
+}
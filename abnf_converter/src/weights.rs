@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+
+use abnf::types::{Node, Rule};
+use anyhow::{anyhow, bail};
+use serde_json::Value;
+
+/// The weights of the alternatives of the ABNF rules, in the order the alternatives are defined.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RuleWeights {
+    pub rules: Vec<(String, Vec<f64>)>,
+}
+
+impl RuleWeights {
+    /// Parses a weight file like `{"rule": [3, 1, 0.5]}`.
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let value: Value = serde_json::from_str(json)?;
+        let Value::Object(map) = value else {
+            bail!("the weights need to be a JSON object");
+        };
+
+        let mut ret = RuleWeights::default();
+        for (rule, weights) in map {
+            let weights = weights
+                .as_array()
+                .and_then(|values| values.iter().map(|v| v.as_f64()).collect())
+                .ok_or_else(|| anyhow!("the weights of `{rule}` need to be a list of numbers"))?;
+            ret.rules.push((rule, weights));
+        }
+        Ok(ret)
+    }
+
+    /// Collects the `; @weights 3 1 0.5` comments from the ABNF source; a comment belongs to the
+    /// rule whose definition it's written in, and the ones of incremental alternatives are
+    /// appended.
+    pub fn from_annotations(abnf: &str) -> anyhow::Result<Self> {
+        let mut ret = RuleWeights::default();
+        let mut rule = None;
+        for (idx, line) in abnf.lines().enumerate() {
+            if line.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                let end = line
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '-')
+                    .unwrap_or(line.len());
+                rule = Some(&line[..end]);
+            }
+            let Some(annotation) = comment(line).and_then(|c| c.trim().strip_prefix("@weights"))
+            else {
+                continue;
+            };
+            let Some(rule) = rule else {
+                bail!("line {}: the weights don't belong to a rule", idx + 1);
+            };
+
+            let weights = annotation
+                .split_whitespace()
+                .map(|w| {
+                    w.parse()
+                        .map_err(|_| anyhow!("line {}: invalid weight `{w}`", idx + 1))
+                })
+                .collect::<anyhow::Result<Vec<f64>>>()?;
+            match ret.rules.iter_mut().find(|(name, _)| name == rule) {
+                Some((_, existing)) => existing.extend(weights),
+                None => ret.rules.push((rule.to_owned(), weights)),
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Adds the weights, replacing the ones given for the same rules.
+    pub fn extend(&mut self, other: RuleWeights) {
+        for (rule, weights) in other.rules {
+            match self.rules.iter_mut().find(|(name, _)| *name == rule) {
+                Some((_, existing)) => *existing = weights,
+                None => self.rules.push((rule, weights)),
+            }
+        }
+    }
+}
+
+/// Returns the comment of an ABNF line, skipping the semicolons in strings and prose values.
+fn comment(line: &str) -> Option<&str> {
+    let mut closing = None;
+    for (idx, c) in line.char_indices() {
+        match (closing, c) {
+            (None, ';') => return Some(&line[idx + 1..]),
+            (None, '"') => closing = Some('"'),
+            (None, '<') => closing = Some('>'),
+            (Some(end), c) if c == end => closing = None,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The weights attached to the alternative nodes of the rules, so that they follow the
+/// alternatives when the subsets and simplifications remove some of them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AlternativeWeights {
+    weights: HashMap<String, Vec<(Node, f64)>>,
+}
+
+impl AlternativeWeights {
+    pub fn new(rules: &[Rule], weights: &RuleWeights) -> anyhow::Result<Self> {
+        let mut ret = AlternativeWeights::default();
+        for (name, rule_weights) in &weights.rules {
+            let Some(rule) = rules.iter().find(|r| r.name() == name) else {
+                bail!("the weighted rule `{name}` is not defined in the grammar");
+            };
+            let alternatives = top_level_alternatives(rule.node());
+            if alternatives.len() != rule_weights.len() {
+                bail!(
+                    "rule `{name}` has {} alternative(s), but {} weight(s)",
+                    alternatives.len(),
+                    rule_weights.len()
+                );
+            }
+            if rule_weights.iter().any(|w| !w.is_finite() || *w < 0.0) {
+                bail!("the weights of rule `{name}` need to be non-negative numbers");
+            }
+            if rule_weights.iter().all(|w| *w == 0.0) {
+                bail!("the weights of rule `{name}` can't all be zero");
+            }
+
+            let weights = alternatives
+                .iter()
+                .map(|&node| node.clone())
+                .zip(rule_weights.iter().copied())
+                .collect();
+            ret.weights.insert(name.clone(), weights);
+        }
+        Ok(ret)
+    }
+
+    pub fn contains(&self, rule: &str) -> bool {
+        self.weights.contains_key(rule)
+    }
+
+    pub fn remove(&mut self, rule: &str) {
+        self.weights.remove(rule);
+    }
+
+    /// Rewrites the weighted alternatives the same way as the rules; the weight of an alternative
+    /// is split evenly between the ones it's rewritten to, and dropped if it's removed.
+    pub fn rewrite(&mut self, mut rewrite: impl FnMut(&Node) -> Vec<Node>) {
+        for weights in self.weights.values_mut() {
+            let mut rewritten = Vec::with_capacity(weights.len());
+            for (node, weight) in weights.iter() {
+                let nodes = rewrite(node);
+                let weight = weight / nodes.len() as f64;
+                rewritten.extend(nodes.into_iter().map(|node| (node, weight)));
+            }
+            *weights = rewritten;
+        }
+    }
+
+    /// Merges the weights of the identical alternatives, which the deduplication merges.
+    pub fn merge_duplicates(&mut self) {
+        for weights in self.weights.values_mut() {
+            let mut merged: Vec<(Node, f64)> = Vec::with_capacity(weights.len());
+            for (node, weight) in weights.drain(..) {
+                match merged.iter_mut().find(|(n, _)| *n == node) {
+                    Some((_, existing)) => *existing += weight,
+                    None => merged.push((node, weight)),
+                }
+            }
+            *weights = merged;
+        }
+    }
+
+    /// The weights of the top-level alternatives of the rule, if it's weighted; fails if the
+    /// alternatives were rewritten without the weights.
+    pub fn get(&self, rule: &Rule) -> anyhow::Result<Option<Vec<f64>>> {
+        let Some(weights) = self.weights.get(rule.name()) else {
+            return Ok(None);
+        };
+        let alternatives = top_level_alternatives(rule.node());
+        if alternatives.len() != weights.len()
+            || alternatives
+                .iter()
+                .zip(weights)
+                .any(|(&alternative, (node, _))| alternative != node)
+        {
+            bail!(
+                "the alternatives of the weighted rule `{}` were changed without their weights",
+                rule.name()
+            );
+        }
+        if weights.iter().all(|(_, weight)| *weight == 0.0) {
+            bail!(
+                "the remaining alternatives of rule `{}` all have a weight of zero",
+                rule.name()
+            );
+        }
+        Ok(Some(weights.iter().map(|(_, weight)| *weight).collect()))
+    }
+}
+
+/// The alternatives of a rule's definition.
+pub fn top_level_alternatives(node: &Node) -> Vec<&Node> {
+    match node {
+        Node::Alternatives(nodes) => nodes.iter().collect(),
+        node => vec![node],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use abnf::rulelist;
+
+    use super::*;
+    use crate::{options::ConversionOptions, ruleset_to_grammar};
+
+    #[test]
+    fn weight_sources() {
+        let abnf = r#"
+; the weights of the statements
+statement = let / if ; @weights 3 1
+          / "<;>" ; @weights 0.5
+let = "let;" ; a plain comment
+if = "if"
+statement =/ "return" ; @weights 2
+"#;
+        let mut weights = RuleWeights::from_annotations(abnf).unwrap();
+        assert_eq!(
+            weights.rules,
+            [("statement".into(), vec![3.0, 1.0, 0.5, 2.0])]
+        );
+        assert!(RuleWeights::from_annotations("; @weights 1\n").is_err());
+        assert!(RuleWeights::from_annotations("a = \"a\" ; @weights x\n").is_err());
+
+        weights.extend(RuleWeights::from_json(r#"{"if": [1], "let": [1]}"#).unwrap());
+        weights.extend(RuleWeights::from_json(r#"{"if": [2]}"#).unwrap());
+        assert_eq!(weights.rules[1], ("if".into(), vec![2.0]));
+        assert!(RuleWeights::from_json(r#"{"if": 2}"#).is_err());
+
+        let rules = rulelist("a = \"x\" / \"y\" / \"z\"\nb = a\n").unwrap();
+        let weights = RuleWeights::from_json(r#"{"a": [1, 0, 5]}"#).unwrap();
+        let alternative_weights = AlternativeWeights::new(&rules, &weights).unwrap();
+        assert!(alternative_weights.contains("a") && !alternative_weights.contains("b"));
+        assert_eq!(
+            alternative_weights.get(&rules[0]).unwrap(),
+            Some(vec![1.0, 0.0, 5.0])
+        );
+        assert_eq!(alternative_weights.get(&rules[1]).unwrap(), None);
+        // the weights need to follow the rewritten alternatives
+        let x_or_y = rulelist("a = \"x\" / \"y\"\n").unwrap();
+        let mut rewritten = alternative_weights.clone();
+        assert!(rewritten.get(&x_or_y[0]).is_err());
+        rewritten.rewrite(|node| match node {
+            Node::String(s) if s.value() == "z" => vec![],
+            node => vec![node.clone(), node.clone()],
+        });
+        rewritten.merge_duplicates();
+        assert_eq!(rewritten.get(&x_or_y[0]).unwrap(), Some(vec![1.0, 0.0]));
+        let y = rulelist("a = \"y\"\n").unwrap();
+        rewritten.rewrite(|node| match node {
+            Node::String(s) if s.value() == "x" => vec![],
+            node => vec![node.clone()],
+        });
+        assert!(rewritten.get(&y[0]).is_err());
+
+        for json in [
+            r#"{"a": [1, 2]}"#,
+            r#"{"a": [0, 0, 0]}"#,
+            r#"{"a": [1, -1, 1]}"#,
+            r#"{"c": [1]}"#,
+        ] {
+            let weights = RuleWeights::from_json(json).unwrap();
+            assert!(AlternativeWeights::new(&rules, &weights).is_err(), "{json}");
+        }
+
+        let abnf = "a = \"x\" [ \"y\" ] / \"z\" ; @weights 4 1\n";
+        let rules = rulelist(abnf).unwrap();
+        let weights = RuleWeights::from_annotations(abnf);
+        let grammar = ruleset_to_grammar(
+            &rules,
+            "a",
+            &ConversionOptions {
+                weights: AlternativeWeights::new(&rules, &weights.unwrap()).unwrap(),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let rule = grammar.rules.iter().find(|r| r.name == "a").unwrap();
+        assert_eq!(rule.probabilities().unwrap(), [0.8, 0.2]);
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::HashSet;
+
+use abnf::types::Rule;
+use anyhow::bail;
+
+use crate::{
+    antlr::lexical_rules, grammar::Symbol, options::WhitespaceMode,
+    validation::collect_rule_references,
+};
+
+/// Which rules get a separator between their concatenated elements, and what it is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WhitespacePolicy {
+    pub mode: WhitespaceMode,
+    /// Either a terminal or a reference to a rule of the grammar.
+    pub separator: Symbol,
+    /// The rules that are separated regardless of the mode.
+    pub separated: Vec<String>,
+    /// The token-level rules that stay glued, along with the rules they use.
+    pub glued: Vec<String>,
+}
+
+impl Default for WhitespacePolicy {
+    fn default() -> Self {
+        Self {
+            mode: WhitespaceMode::None,
+            separator: Symbol::Terminal(" ".into()),
+            separated: Vec::new(),
+            glued: Vec::new(),
+        }
+    }
+}
+
+impl WhitespacePolicy {
+    /// Returns the names of the rules whose elements need to be separated.
+    pub fn separated_rules<'a>(
+        &self,
+        rules: &'a [Rule],
+        start: &str,
+    ) -> anyhow::Result<HashSet<&'a str>> {
+        let defined = rules.iter().map(|r| r.name()).collect::<HashSet<_>>();
+        let separator_rule = match &self.separator {
+            Symbol::NonTerminal(name) => Some(name),
+            _ => None,
+        };
+        for rule in self
+            .separated
+            .iter()
+            .chain(&self.glued)
+            .chain(separator_rule)
+        {
+            if !defined.contains(rule.as_str()) {
+                bail!("the whitespace rule `{rule}` is not defined in the grammar");
+            }
+        }
+
+        let mut ret = match self.mode {
+            WhitespaceMode::None => HashSet::new(),
+            WhitespaceMode::All => defined,
+            WhitespaceMode::Syntactic => {
+                let lexical = lexical_rules(rules, start);
+                defined.difference(&lexical).copied().collect()
+            }
+        };
+        ret.extend(
+            rules
+                .iter()
+                .map(|r| r.name())
+                .filter(|name| self.separated.iter().any(|s| s == name)),
+        );
+
+        // anything used by a token, including the separator itself, is a part of a token
+        let mut queue = self
+            .glued
+            .iter()
+            .chain(separator_rule)
+            .map(|s| s.as_str())
+            .collect::<Vec<_>>();
+        let mut glued = HashSet::new();
+        while let Some(name) = queue.pop() {
+            if glued.insert(name) {
+                for rule in rules.iter().filter(|r| r.name() == name) {
+                    collect_rule_references(rule.node(), &mut queue);
+                }
+            }
+        }
+        ret.retain(|name| !glued.contains(name));
+
+        Ok(ret)
+    }
+}
+
+/// Whether two concatenated sequences of symbols need a separator in between; the ones already
+/// separated by whitespace or by the separator itself don't. The empty terminals don't count, so
+/// they neither get a separator of their own nor hide the one that the symbols around them need.
+pub fn needs_separator(prefix: &[Symbol], suffix: &[Symbol], separator: &Symbol) -> bool {
+    let is_empty = |symbol: &&Symbol| matches!(symbol, Symbol::Terminal(s) if s.is_empty());
+    let prev = prefix.iter().rev().find(|symbol| !is_empty(symbol));
+    let next = suffix.iter().find(|symbol| !is_empty(symbol));
+    match (prev, next) {
+        (Some(prev), _) | (_, Some(prev)) if prev == separator => false,
+        (Some(Symbol::Terminal(prev)), _) if prev.ends_with(char::is_whitespace) => false,
+        (_, Some(Symbol::Terminal(next))) if next.starts_with(char::is_whitespace) => false,
+        (Some(_), Some(_)) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use abnf::rulelist;
+
+    use super::*;
+    use crate::{
+        options::{ConversionOptions, WhitespaceMode},
+        ruleset_to_grammar,
+    };
+
+    #[test]
+    fn separators() {
+        let rules = rulelist(
+            r#"
+instruction = "add" 1*2( ws operand ) " into " register [ ";" ]
+operand = register / literal
+register = "r" 1*digit
+literal = 1*digit "u8"
+digit = "0" / "1"
+ws = 1*" "
+"#,
+        )
+        .unwrap();
+        let t = |s: &str| Symbol::Terminal(s.into());
+        let nt = |s: &str| Symbol::NonTerminal(s.into());
+
+        let mut policy = WhitespacePolicy {
+            mode: WhitespaceMode::Syntactic,
+            ..Default::default()
+        };
+        let separated = policy.separated_rules(&rules, "instruction").unwrap();
+        assert_eq!(separated, HashSet::from(["instruction"]));
+
+        policy.mode = WhitespaceMode::All;
+        policy.separator = nt("ws");
+        policy.glued = vec!["register".into()];
+        let separated = policy.separated_rules(&rules, "instruction").unwrap();
+        assert_eq!(
+            separated,
+            HashSet::from(["instruction", "operand", "literal"])
+        );
+
+        let grammar = ruleset_to_grammar(
+            &rules,
+            "instruction",
+            &ConversionOptions {
+                whitespace: policy.clone(),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let instruction = grammar
+            .rules
+            .iter()
+            .find(|r| r.name == "instruction")
+            .unwrap();
+        assert_eq!(
+            instruction.alternatives[0],
+            [
+                t("add"),
+                nt("ws"),
+                nt("between-1-and-2-（ws-and-operand）"),
+                t(" into "),
+                nt("register"),
+                nt("ws"),
+                nt("optional-;")
+            ]
+        );
+        // the separator isn't repeated next to itself
+        let group = grammar
+            .rules
+            .iter()
+            .find(|r| r.name == "ws-and-operand")
+            .unwrap();
+        assert_eq!(group.alternatives[0], [nt("ws"), nt("operand")]);
+        let literal = grammar.rules.iter().find(|r| r.name == "literal").unwrap();
+        assert_eq!(
+            literal.alternatives[0],
+            [nt("at-least-1-digit"), nt("ws"), t("u8")]
+        );
+        let register = grammar.rules.iter().find(|r| r.name == "register").unwrap();
+        assert_eq!(register.alternatives[0], [t("r"), nt("at-least-1-digit")]);
+
+        // the empty terminals are skipped
+        let separator = t(" ");
+        assert!(!needs_separator(&[t("a")], &[t("")], &separator));
+        assert!(needs_separator(
+            &[t("a"), t("")],
+            &[t(""), t("b")],
+            &separator
+        ));
+        assert!(!needs_separator(&[t("a ")], &[t(""), t("b")], &separator));
+
+        policy.glued.push("undefined".into());
+        assert!(policy.separated_rules(&rules, "instruction").is_err());
+    }
+}
@@ -0,0 +1,183 @@
+use std::{env, fs, path::Path, process::Command};
+
+const FORMATS: [&str; 5] = ["json", "nautilus", "antlr", "dict", "grammar-mutator"];
+
+/// Describes the first line that differs between the snapshot and the new result.
+fn first_difference(expected: &str, actual: &str) -> String {
+    let (mut expected_lines, mut actual_lines) = (expected.lines(), actual.lines());
+    for line in 1.. {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(e), Some(a)) if e == a => {}
+            (e, a) => {
+                let (e, a) = (e.unwrap_or("<end of file>"), a.unwrap_or("<end of file>"));
+                return format!("line {line}:\n- {e}\n+ {a}");
+            }
+        }
+    }
+    unreachable!()
+}
+
+/// Runs the converter on the fixture, and returns its output.
+fn run(input: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_abnf_converter"))
+        .arg(input)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "couldn't run {} {}:\n{}",
+        input.display(),
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Reads the rule ids of a LibAFL Nautilus input, which are written first, as postcard varints.
+fn nautilus_rules(input: &[u8]) -> Vec<usize> {
+    let mut bytes = input.iter();
+    let mut varint = || {
+        let mut n = 0;
+        for shift in (0..).step_by(7) {
+            let byte = *bytes.next().unwrap();
+            n |= usize::from(byte & 0x7F) << shift;
+            if byte < 0x80 {
+                break;
+            }
+        }
+        n
+    };
+    let count = varint();
+    (0..count)
+        .map(|_| {
+            assert_eq!(varint(), 0, "the rules are `RuleIdOrCustom::Rule`s");
+            varint()
+        })
+        .collect()
+}
+
+/// Derives the text of the Nautilus rules given by their ids in pre-order.
+fn nautilus_text(rules: &[(String, String)], ids: &mut impl Iterator<Item = usize>) -> String {
+    let (_, body) = &rules[ids.next().unwrap()];
+    let mut ret = String::new();
+    let mut chars = body.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => ret.extend(chars.next()),
+            '{' => {
+                chars.by_ref().find(|&c| c == '}');
+                ret.push_str(&nautilus_text(rules, ids));
+            }
+            c => ret.push(c),
+        }
+    }
+    ret
+}
+
+/// Generates seeds from the fixture and parses them back; every seed needs to parse into a tree
+/// that derives it from the Nautilus rules written along with the trees. The result lists every
+/// seed along with the rule ids of its tree.
+fn parsed_seeds(fixture: &str, input: &Path, args: &[&str]) -> String {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(fixture);
+    let (seeds, trees) = (dir.join("seeds"), dir.join("trees"));
+    let _ = fs::remove_dir_all(&dir);
+
+    let seeds_arg = seeds.to_str().unwrap();
+    let trees_arg = trees.to_str().unwrap();
+    let generate = ["--generate", "10", "--max-length", "512", "-o", seeds_arg];
+    run(input, &[args, &generate].concat());
+    run(
+        input,
+        &[args, &["--parse", seeds_arg, "-o", trees_arg]].concat(),
+    );
+
+    let mut names = fs::read_dir(&seeds)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    names.sort();
+    let nautilus = fs::read_to_string(trees.join("nautilus.json")).unwrap();
+    let nautilus: Vec<(String, String)> = serde_json::from_str(&nautilus).unwrap();
+    let mut ret = String::new();
+    for name in names {
+        let seed = fs::read_to_string(seeds.join(&name)).unwrap();
+        let tree = trees.join("corpus").join(&name);
+        assert!(
+            tree.exists(),
+            "the seed {name} of {fixture} doesn't parse: {seed:?}"
+        );
+        let rules = nautilus_rules(&fs::read(tree).unwrap());
+        assert_eq!(
+            nautilus_text(&nautilus, &mut rules.iter().copied()),
+            seed,
+            "the tree of the seed {name} of {fixture} doesn't derive it"
+        );
+        ret.push_str(&format!("{name}: {}\n", serde_json::Value::from(seed)));
+        ret.push_str(&format!("nautilus rules: {rules:?}\n"));
+    }
+    ret
+}
+
+/// Converts the fixture to every output format, and parses seeds generated from it back; the
+/// results are compared with the snapshots in `tests/snapshots`. Run with `UPDATE_SNAPSHOTS=1` to
+/// overwrite the snapshots instead.
+fn check_snapshots(fixture: &str, args: &[&str]) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let input = dir.join("fixtures").join(format!("{fixture}.abnf"));
+    let update = env::var_os("UPDATE_SNAPSHOTS").is_some_and(|value| value == "1");
+    // the ranges are sampled down to keep the snapshots readable
+    let args = [args, &["--max-range-size", "16"]].concat();
+
+    let mut results = FORMATS
+        .iter()
+        .map(|format| {
+            (
+                *format,
+                run(&input, &[&args, &["--format", format][..]].concat()),
+            )
+        })
+        .collect::<Vec<_>>();
+    results.push(("seeds", parsed_seeds(fixture, &input, &args)));
+
+    let mut mismatches = Vec::new();
+    for (extension, actual) in results {
+        let path = dir.join("snapshots").join(format!("{fixture}.{extension}"));
+        if update {
+            fs::write(&path, &actual).unwrap();
+            continue;
+        }
+        match fs::read_to_string(&path) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => mismatches.push(format!(
+                "{} differs at {}",
+                path.display(),
+                first_difference(&expected, &actual)
+            )),
+            Err(_) => mismatches.push(format!("{} is missing", path.display())),
+        }
+    }
+
+    assert!(
+        mismatches.is_empty(),
+        "{}\nrun the tests with UPDATE_SNAPSHOTS=1 to update the snapshots",
+        mismatches.join("\n")
+    );
+}
+
+#[test]
+#[ignore = "needs snarkVM's aleo-instructions.abnf checked in verbatim as \
+            tests/fixtures/aleo_instructions.abnf, with its source and version noted"]
+fn aleo_instructions() {
+    check_snapshots("aleo_instructions", &["--start", "program"]);
+}
+
+#[test]
+fn uri() {
+    check_snapshots("uri", &[]);
+}
+
+#[test]
+fn json() {
+    check_snapshots("json", &[]);
+}